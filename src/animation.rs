@@ -0,0 +1,97 @@
+use crate::{helper, Entries, Source};
+use std::{io, fs::{self, File}, path::Path};
+use icon_baker::{
+    AsSize, SourceImage,
+    image::{self, AnimationDecoder, DynamicImage, RgbaImage}
+};
+use gif::SetParameter;
+
+/// The folder animated favicons are saved in, relative to the output folder.
+pub const FOLDER: &str = "animated";
+
+/// The speed of the quantizer reducing frames to a palette, from `1` to `30`.
+const QUANTIZER_SPEED: i32 = 10;
+
+/// A frame of an animation, along with its delay in milliseconds.
+type Frame = (RgbaImage, u16);
+
+/// Renders every size of the entries whose sources are animated `gif` files frame by
+/// frame, saves them as animated `gif` files in the `animated` folder of `path` and
+/// links them from the HTML helper of the favicon saved there. The `png` entries of
+/// the favicon act as static fallbacks, showing the first frame.
+pub fn save<K: AsSize>(entries: &Entries<K>, path: &Path) -> io::Result<()> {
+    let mut links = String::new();
+
+    for (key, source, config) in entries {
+        let frames = match source {
+            Source::Path(source) => match frames(source)? {
+                Some(frames) => frames,
+                None => continue
+            },
+            Source::Glyph(_) => continue
+        };
+
+        let size = key.as_size();
+        let mut rendered = Vec::with_capacity(frames.len());
+        for (frame, delay) in frames {
+            let frame = SourceImage::from(DynamicImage::ImageRgba8(frame));
+            rendered.push((config.render(&frame, size)?.to_rgba(), delay));
+        }
+
+        let name = format!("favicon-{}.gif", size);
+        fs::create_dir_all(path.join(FOLDER))?;
+        encode(&rendered, File::create(path.join(FOLDER).join(&name))?)?;
+
+        links.push_str(&format!(
+            "<link rel=\"icon\" type=\"image/gif\" sizes=\"{0}x{0}\" href=\"{1}/{2}\">\n",
+            size, FOLDER, name
+        ));
+    }
+
+    helper::append(path, &links)
+}
+
+/// Decodes the frames of the `gif` file at `path`. Returns `None` if it isn't a `gif`
+/// file, or if it only holds a single frame.
+fn frames(path: &Path) -> io::Result<Option<Vec<Frame>>> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("gif") => (),
+        _ => return Ok(None)
+    }
+
+    let frames = image::gif::Decoder::new(File::open(path)?)
+        .and_then(|decoder| decoder.into_frames().collect_frames())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    if frames.len() < 2 { return Ok(None); }
+
+    Ok(Some(frames.into_iter().map(|frame| {
+        let delay = frame.delay().to_integer();
+        (frame.into_buffer(), delay)
+    }).collect()))
+}
+
+/// Encodes `frames` as a looping animated `gif`. Since `gif` only supports binary
+/// transparency, semi-transparent pixels are made either opaque or transparent.
+fn encode<W: io::Write>(frames: &[Frame], w: W) -> io::Result<()> {
+    let (width, height) = match frames.first() {
+        Some((frame, _)) => (frame.width() as u16, frame.height() as u16),
+        None => return Ok(())
+    };
+
+    let mut encoder = gif::Encoder::new(w, width, height, &[])?;
+    encoder.set(gif::Repeat::Infinite)?;
+
+    for (frame, delay) in frames {
+        let mut pixels = frame.clone().into_raw();
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, QUANTIZER_SPEED);
+
+        // `gif` delays are measured in hundredths of a second
+        frame.delay = delay / 10;
+        frame.dispose = gif::DisposalMethod::Background;
+
+        encoder.write_frame(&frame)?;
+    }
+
+    Ok(())
+}
\ No newline at end of file
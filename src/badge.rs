@@ -0,0 +1,83 @@
+use crate::{compose, font::{self, GLYPH_HEIGHT}};
+use icon_baker::image::{RgbaImage, Rgba};
+
+/// The number of samples taken along each axis of a pixel when computing the
+/// coverage of a badge.
+const SUBSAMPLES: u32 = 4;
+
+/// Entries smaller than this are left without a badge, unless specified otherwise.
+pub const DEFAULT_MIN_SIZE: u32 = 32;
+
+pub const DEFAULT_COLOR: Rgba<u8> = Rgba([0xE5, 0x39, 0x35, 0xFF]);
+pub const DEFAULT_TEXT_COLOR: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+
+/// A text label overlaid on every entry, such as `BETA`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Badge {
+    pub text: String,
+    pub style: Style,
+    pub color: Rgba<u8>,
+    pub text_color: Rgba<u8>,
+    /// Entries smaller than this are left without a badge.
+    pub min_size: u32
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// A horizontal band along the bottom edge.
+    Ribbon,
+    /// A diagonal band across the top-right corner.
+    Corner
+}
+
+impl Badge {
+    /// Overlays the badge on `image`, unless it is smaller than `min_size`.
+    pub fn overlay(&self, image: &mut RgbaImage) {
+        let size = image.width();
+        if size < self.min_size { return; }
+
+        let size = size as f32;
+        let text = font::glyphs(&self.text);
+        let (band, text_len) = match self.style {
+            Style::Ribbon => (size * 0.25, size * 0.9),
+            Style::Corner => (size * 0.18, size * 0.55)
+        };
+
+        let text_width = font::width(&text) as f32;
+        let scale = (band * 0.6 / GLYPH_HEIGHT as f32).min(text_len / text_width);
+
+        for (x, y, px) in image.enumerate_pixels_mut() {
+            let (mut band_coverage, mut text_coverage) = (0, 0);
+
+            for i in 0..SUBSAMPLES {
+                for j in 0..SUBSAMPLES {
+                    let sx = x as f32 + (i as f32 + 0.5) / SUBSAMPLES as f32;
+                    let sy = y as f32 + (j as f32 + 0.5) / SUBSAMPLES as f32;
+
+                    // Coordinates along and across the band, relative to its center
+                    let (u, v) = match self.style {
+                        Style::Ribbon => (sx - size / 2.0, sy - (size - band / 2.0)),
+                        Style::Corner => {
+                            let (cx, cy) = (size - sx, sy);
+                            ((cy - cx) / 2f32.sqrt(), (cx + cy) / 2f32.sqrt() - size * 0.3)
+                        }
+                    };
+
+                    if v.abs() > band / 2.0 { continue; }
+                    band_coverage += 1;
+
+                    let tx = u / scale + text_width / 2.0;
+                    let ty = v / scale + GLYPH_HEIGHT as f32 / 2.0;
+                    if font::lit(&text, tx, ty) { text_coverage += 1; }
+                }
+            }
+
+            let samples = (SUBSAMPLES * SUBSAMPLES) as f32;
+            let band_alpha = band_coverage as f32 / samples;
+            let text_alpha = text_coverage as f32 / samples;
+
+            compose::blend(px, self.color, band_alpha);
+            compose::blend(px, self.text_color, text_alpha);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,463 @@
+use crate::sample::from_linear;
+use std::{io, fs, path::Path};
+use icon_baker::{SourceImage, image::{DynamicImage, RgbaImage}};
+
+pub const PNG_SIGNATURE: [u8;8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// The identifier of the `APP2` segments holding the ICC profile of a `jpeg` file.
+const JPEG_ICC_MARKER: &[u8] = b"ICC_PROFILE\0";
+
+/// The D50 white point of the profile connection space.
+const D50: [f32;3] = [0.9642, 1.0, 0.8249];
+
+/// The linear sRGB to XYZ matrix, adapted to D50.
+const SRGB: [[f32;3];3] = [
+    [0.436_074_7, 0.385_064_9, 0.143_080_4],
+    [0.222_504_5, 0.716_878_6, 0.060_616_9],
+    [0.013_932_2, 0.097_104_5, 0.714_173_3]
+];
+
+/// The linear Display P3 to XYZ matrix, adapted to D50.
+const DISPLAY_P3: [[f32;3];3] = [
+    [0.515_118_7, 0.291_977_8, 0.157_103_5],
+    [0.241_189_2, 0.692_244_1, 0.066_566_8],
+    [-0.001_050_5, 0.041_879_1, 0.784_071_3]
+];
+
+/// The parameters of the sRGB transfer function, as a `para` curve.
+const SRGB_CURVE: [f32;7] = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.040_45, 0.0, 0.0];
+
+/// The Bradford adaptation from D65 to D50, stored in the `chad` tag of generated profiles.
+const D65_TO_D50: [[f32;3];3] = [
+    [0.955_576_6, -0.023_039_3, 0.063_163_6],
+    [-0.028_289_5, 1.009_941_6, 0.021_007_7],
+    [0.012_298_2, -0.020_483_0, 1.329_909_8]
+];
+
+/// The color space of the entries of an icon. Both spaces share the sRGB transfer
+/// function, and differ only in their primaries.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    DisplayP3
+}
+
+/// An RGB matrix/TRC ICC profile. Profiles based on lookup tables aren't supported.
+#[derive(Clone, Debug, PartialEq)]
+struct Profile {
+    /// Converts linear RGB to XYZ, relative to D50.
+    matrix: [[f32;3];3],
+    curves: [Curve;3]
+}
+
+/// A tone reproduction curve, converting encoded channels to linear light.
+#[derive(Clone, Debug, PartialEq)]
+enum Curve {
+    Gamma(f32),
+    /// Evenly spaced samples, linearly interpolated.
+    Table(Vec<f32>),
+    /// A `para` curve, as `(g, a, b, c, d, e, f)`.
+    Parametric(u16, [f32;7])
+}
+
+impl ColorSpace {
+    fn matrix(self) -> [[f32;3];3] {
+        match self {
+            ColorSpace::Srgb => SRGB,
+            ColorSpace::DisplayP3 => DISPLAY_P3
+        }
+    }
+
+    fn profile(self) -> Profile {
+        let curve = Curve::Parametric(3, SRGB_CURVE);
+        Profile { matrix: self.matrix(), curves: [curve.clone(), curve.clone(), curve] }
+    }
+
+    /// The ICC profile output entries are tagged with, if any. Untagged entries are
+    /// assumed to be sRGB.
+    pub fn icc(self) -> Option<Vec<u8>> {
+        match self {
+            ColorSpace::Srgb => None,
+            ColorSpace::DisplayP3 => Some(icc(DISPLAY_P3, "Display P3"))
+        }
+    }
+}
+
+impl Profile {
+    /// Parses the matrix/TRC tags of an RGB display or input profile.
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 132 || &data[16..20] != b"RGB " || &data[20..24] != b"XYZ " {
+            return None;
+        }
+
+        let count = u32_at(data, 128)? as usize;
+        let tag = |sig: &[u8]| (0..count).find_map(|i| {
+            let entry = 132 + 12 * i;
+            if data.get(entry..entry + 4)? != sig { return None; }
+
+            let offset = u32_at(data, entry + 4)? as usize;
+            let size = u32_at(data, entry + 8)? as usize;
+            data.get(offset..offset.checked_add(size)?)
+        });
+
+        let (r, g, b) = (xyz(tag(b"rXYZ")?)?, xyz(tag(b"gXYZ")?)?, xyz(tag(b"bXYZ")?)?);
+        let matrix = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+        let curves = [curve(tag(b"rTRC")?)?, curve(tag(b"gTRC")?)?, curve(tag(b"bTRC")?)?];
+
+        Some(Profile { matrix, curves })
+    }
+}
+
+impl Curve {
+    fn eval(&self, x: f32) -> f32 {
+        match self {
+            Curve::Gamma(gamma) => x.powf(*gamma),
+            Curve::Table(table) if table.is_empty() => x,
+            Curve::Table(table) => {
+                let pos = x * (table.len() - 1) as f32;
+                let i = (pos.floor() as usize).min(table.len() - 1);
+                let j = (i + 1).min(table.len() - 1);
+
+                table[i] + (table[j] - table[i]) * (pos - i as f32)
+            },
+            Curve::Parametric(kind, [g, a, b, c, d, e, f]) => match kind {
+                0 => x.powf(*g),
+                1 if x >= -b / a => (a * x + b).powf(*g),
+                1 => 0.0,
+                2 if x >= -b / a => (a * x + b).powf(*g) + c,
+                2 => *c,
+                3 if x >= *d => (a * x + b).powf(*g),
+                3 => c * x,
+                _ if x >= *d => (a * x + b).powf(*g) + e,
+                _ => c * x + f
+            }
+        }
+    }
+}
+
+/// Opens the image at `path` and converts it from its embedded ICC profile to
+/// `color_space`. Raster images without a supported profile are assumed to be sRGB,
+/// as are `svg` files, which are converted once rasterized.
+pub fn open(path: &Path, color_space: ColorSpace) -> io::Result<SourceImage> {
+    let source = SourceImage::open(path)?;
+    let image = match source {
+        SourceImage::Raster(image) => image,
+        SourceImage::Svg(_) => return Ok(source)
+    };
+
+    let converted = match embedded_profile(&fs::read(path)?).and_then(|icc| Profile::parse(&icc)) {
+        Some(profile) => convert(&image.to_rgba(), &profile, color_space),
+        None if color_space != ColorSpace::Srgb => from_srgb(&image.to_rgba(), color_space),
+        None => return Ok(SourceImage::from(image))
+    };
+
+    Ok(SourceImage::from(DynamicImage::ImageRgba8(converted)))
+}
+
+/// Converts an sRGB `image` to `color_space`.
+pub fn from_srgb(image: &RgbaImage, color_space: ColorSpace) -> RgbaImage {
+    convert(image, &ColorSpace::Srgb.profile(), color_space)
+}
+
+/// Converts an `image` in `color_space` back to sRGB.
+pub fn to_srgb(image: &RgbaImage, color_space: ColorSpace) -> RgbaImage {
+    convert(image, &color_space.profile(), ColorSpace::Srgb)
+}
+
+/// Converts `image` from `profile` to `color_space`. Colors outside of the gamut of
+/// `color_space` are clipped.
+fn convert(image: &RgbaImage, profile: &Profile, color_space: ColorSpace) -> RgbaImage {
+    let matrix = multiply(&inverse(&color_space.matrix()), &profile.matrix);
+    let mut luts = [[0f32;256];3];
+
+    for (lut, curve) in luts.iter_mut().zip(profile.curves.iter()) {
+        for (i, value) in lut.iter_mut().enumerate() {
+            *value = curve.eval(i as f32 / 255.0);
+        }
+    }
+
+    let mut output = image.clone();
+    for px in output.pixels_mut() {
+        let rgb = [luts[0][px.0[0] as usize], luts[1][px.0[1] as usize], luts[2][px.0[2] as usize]];
+
+        for (ch, row) in matrix.iter().enumerate() {
+            let c = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+            px.0[ch] = from_linear(c.clamp(0.0, 1.0));
+        }
+    }
+
+    output
+}
+
+/// Extracts the ICC profile embedded in a `png` or `jpeg` file, if any.
+fn embedded_profile(data: &[u8]) -> Option<Vec<u8>> {
+    if data.starts_with(&PNG_SIGNATURE) {
+        let (_, chunk) = png_chunks(data).find(|(kind, _)| kind == b"iCCP")?;
+
+        // The chunk holds a null-terminated name and a compression method
+        let name = chunk.iter().position(|&b| b == 0)?;
+        inflate::inflate_bytes_zlib(chunk.get(name + 2..)?).ok()
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg_profile(data)
+    } else {
+        None
+    }
+}
+
+/// Concatenates the `APP2` segments holding the ICC profile of a `jpeg` file, in order.
+fn jpeg_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let mut segments = Vec::new();
+    let mut i = 2;
+
+    while i + 4 <= data.len() && data[i] == 0xFF {
+        let marker = data[i + 1];
+        let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+
+        // Entropy coded data follows the start of scan segment
+        if marker == 0xDA { break; }
+
+        let segment = data.get(i + 4..i + 2 + len)?;
+        if marker == 0xE2 && segment.starts_with(JPEG_ICC_MARKER) && segment.len() > 14 {
+            segments.push((segment[12], &segment[14..]));
+        }
+
+        i += 2 + len;
+    }
+
+    if segments.is_empty() { return None; }
+
+    segments.sort_by_key(|&(seq, _)| seq);
+    Some(segments.into_iter().flat_map(|(_, segment)| segment.iter().cloned()).collect())
+}
+
+/// Embeds `icc` in a `png` file, replacing any color space information it holds.
+pub fn tag_png(data: &[u8], icc: &[u8], name: &str) -> Vec<u8> {
+    let mut iccp = Vec::with_capacity(name.len() + 2 + icc.len());
+    iccp.extend_from_slice(name.as_bytes());
+    iccp.extend_from_slice(&[0, 0]);
+    iccp.extend(deflate::deflate_bytes_zlib(icc));
+
+    let mut output = Vec::with_capacity(data.len() + iccp.len() + 12);
+    output.extend_from_slice(&PNG_SIGNATURE);
+
+    for (kind, chunk) in png_chunks(data) {
+        if kind == b"iCCP" || kind == b"sRGB" || kind == b"gAMA" || kind == b"cHRM" {
+            continue;
+        }
+
+        write_chunk(&mut output, kind, chunk);
+
+        // `iCCP` must precede `PLTE` and `IDAT`, so it's placed right after `IHDR`
+        if kind == b"IHDR" {
+            write_chunk(&mut output, b"iCCP", &iccp);
+        }
+    }
+
+    output
+}
+
+/// Iterates over the `(type, data)` pairs of the chunks of a `png` file.
+pub fn png_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut i = PNG_SIGNATURE.len();
+
+    std::iter::from_fn(move || {
+        let len = u32_at(data, i)? as usize;
+        let kind = data.get(i + 4..i + 8)?;
+        let chunk = data.get(i + 8..i + 8 + len)?;
+
+        i += 12 + len;
+        Some((kind, chunk))
+    })
+}
+
+pub fn write_chunk(output: &mut Vec<u8>, kind: &[u8], chunk: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(chunk);
+
+    output.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(chunk);
+    output.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+/// Generates an ICC v4 display profile with the primaries of `matrix` and the sRGB
+/// transfer function.
+fn icc(matrix: [[f32;3];3], description: &str) -> Vec<u8> {
+    let column = |i: usize| xyz_tag(&[matrix[0][i], matrix[1][i], matrix[2][i]]);
+    let chad: Vec<f32> = D65_TO_D50.iter().flat_map(|row| row.iter().cloned()).collect();
+
+    let mut trc = b"para\0\0\0\0\0\x03\0\0".to_vec();
+    for &param in SRGB_CURVE[..5].iter() {
+        trc.extend_from_slice(&s15_fixed16(param));
+    }
+
+    let mut chad_tag = b"sf32\0\0\0\0".to_vec();
+    for &value in chad.iter() {
+        chad_tag.extend_from_slice(&s15_fixed16(value));
+    }
+
+    let tags: [(&[u8;4], Vec<u8>);9] = [
+        (b"desc", mluc(description)),
+        (b"cprt", mluc("No copyright, use freely")),
+        (b"wtpt", xyz_tag(&D50)),
+        (b"chad", chad_tag),
+        (b"rXYZ", column(0)),
+        (b"gXYZ", column(1)),
+        (b"bXYZ", column(2)),
+        (b"rTRC", trc.clone()),
+        (b"gTRC", trc.clone()),
+    ];
+
+    // The blue curve shares the data of the red one
+    let n_tags = tags.len() + 1;
+    let mut table = Vec::with_capacity(4 + 12 * n_tags);
+    let mut body = Vec::new();
+    let base = 128 + 4 + 12 * n_tags;
+
+    table.extend_from_slice(&(n_tags as u32).to_be_bytes());
+    let mut trc_entry = [0u8;8];
+    for (sig, data) in tags.iter() {
+        let offset = (base + body.len()) as u32;
+        if *sig == b"rTRC" {
+            trc_entry[..4].copy_from_slice(&offset.to_be_bytes());
+            trc_entry[4..].copy_from_slice(&(data.len() as u32).to_be_bytes());
+        }
+
+        table.extend_from_slice(*sig);
+        table.extend_from_slice(&offset.to_be_bytes());
+        table.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        body.extend_from_slice(data);
+
+        // Tag data is aligned to 4 bytes
+        while body.len() % 4 != 0 { body.push(0); }
+    }
+
+    table.extend_from_slice(b"bTRC");
+    table.extend_from_slice(&trc_entry);
+
+    let size = (128 + table.len() + body.len()) as u32;
+    let mut header = Vec::with_capacity(128);
+    header.extend_from_slice(&size.to_be_bytes());
+    header.extend_from_slice(&[0;4]);
+    header.extend_from_slice(&[0x04, 0x30, 0, 0]);
+    header.extend_from_slice(b"mntrRGB XYZ ");
+    // The creation date is left blank so that outputs are reproducible
+    header.extend_from_slice(&[0;12]);
+    header.extend_from_slice(b"acspAPPL");
+    header.extend_from_slice(&[0;24]);
+    header.extend_from_slice(&xyz_tag(&D50)[8..]);
+    header.resize(128, 0);
+
+    header.extend(table);
+    header.extend(body);
+    header
+}
+
+/// Encodes `text` as a single record `mluc` tag.
+fn mluc(text: &str) -> Vec<u8> {
+    let utf16: Vec<u8> = text.encode_utf16().flat_map(|c| c.to_be_bytes().to_vec()).collect();
+
+    let mut tag = b"mluc\0\0\0\0".to_vec();
+    tag.extend_from_slice(&1u32.to_be_bytes());
+    tag.extend_from_slice(&12u32.to_be_bytes());
+    tag.extend_from_slice(b"enUS");
+    tag.extend_from_slice(&(utf16.len() as u32).to_be_bytes());
+    tag.extend_from_slice(&28u32.to_be_bytes());
+    tag.extend(utf16);
+    tag
+}
+
+fn xyz_tag(xyz: &[f32;3]) -> Vec<u8> {
+    let mut tag = b"XYZ \0\0\0\0".to_vec();
+    for &value in xyz.iter() {
+        tag.extend_from_slice(&s15_fixed16(value));
+    }
+
+    tag
+}
+
+fn s15_fixed16(value: f32) -> [u8;4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+/// Parses an `XYZ ` tag.
+fn xyz(tag: &[u8]) -> Option<[f32;3]> {
+    if tag.get(..4)? != b"XYZ " { return None; }
+
+    Some([fixed_at(tag, 8)?, fixed_at(tag, 12)?, fixed_at(tag, 16)?])
+}
+
+/// Parses a `curv` or `para` tag.
+fn curve(tag: &[u8]) -> Option<Curve> {
+    match tag.get(..4)? {
+        b"curv" => {
+            let count = u32_at(tag, 8)? as usize;
+            let entry = |i: usize| -> Option<u16> {
+                Some(u16::from_be_bytes([*tag.get(12 + 2 * i)?, *tag.get(13 + 2 * i)?]))
+            };
+
+            match count {
+                1 => Some(Curve::Gamma(f32::from(entry(0)?) / 256.0)),
+                _ => (0..count)
+                    .map(|i| entry(i).map(|v| f32::from(v) / 65535.0))
+                    .collect::<Option<_>>()
+                    .map(Curve::Table)
+            }
+        },
+        b"para" => {
+            let kind = u16::from_be_bytes([*tag.get(8)?, *tag.get(9)?]);
+            let n_params = match kind { 0 => 1, 1 => 3, 2 => 4, 3 => 5, 4 => 7, _ => return None };
+
+            let mut params = [0f32;7];
+            for (i, param) in params.iter_mut().enumerate().take(n_params) {
+                *param = fixed_at(tag, 12 + 4 * i)?;
+            }
+
+            Some(Curve::Parametric(kind, params))
+        },
+        _ => None
+    }
+}
+
+fn u32_at(data: &[u8], i: usize) -> Option<u32> {
+    let bytes = data.get(i..i + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads an `s15Fixed16Number`.
+fn fixed_at(data: &[u8], i: usize) -> Option<f32> {
+    Some(u32_at(data, i)? as i32 as f32 / 65536.0)
+}
+
+fn multiply(a: &[[f32;3];3], b: &[[f32;3];3]) -> [[f32;3];3] {
+    let mut output = [[0f32;3];3];
+
+    for (i, row) in output.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+
+    output
+}
+
+fn inverse(m: &[[f32;3];3]) -> [[f32;3];3] {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+
+    let det = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    let mut output = [[0f32;3];3];
+
+    for (i, row) in output.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            // The inverse is the transposed matrix of cofactors over the determinant
+            *value = cofactor(j, i) / det;
+        }
+    }
+
+    output
+}
\ No newline at end of file
@@ -0,0 +1,211 @@
+use super::{save_favicon, IcoConfig, IcnsConfig, FaviconConfig};
+use crate::{error::Error, encode::{self, Encode, optimize}, Entries, Output};
+use std::{io, env, fs, process, path::Path, fmt::{self, Display, Formatter}};
+use icon_baker::{favicon::Favicon, Icon, AsSize};
+use crossterm::{style, Color};
+
+/// The qualities favicons are quantized at, in turn.
+const QUALITIES: [u8;4] = [90, 70, 50, 30];
+
+/// The sizes linked as apple-touch icons by the HTML helper of favicons.
+const APPLE_TOUCH_SIZES: [u32;4] = [76, 120, 152, 180];
+
+/// The sizes expected by the web app manifest of installable web apps.
+const WEB_APP_SIZES: [u32;2] = [192, 512];
+
+/// A step of the escalation of `--max-bytes`, applied on top of the previous ones.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Step {
+    /// Every `png` encoded entry is optimized at the highest level.
+    Optimize,
+    /// Every entry of a favicon is quantized at the given quality.
+    Quantize(u8),
+    /// Every 32-bit entry of an `ico` file is stored as whichever of `bmp` and `png`
+    /// is smaller.
+    Smallest,
+    /// The entries of the given size are dropped.
+    Drop(u32)
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Step::Optimize => write!(f, "--optimize {}", optimize::MAX_LEVEL),
+            Step::Quantize(quality) => write!(f, "--quantize {}", quality),
+            Step::Smallest => write!(f, "bmp or png entries, whichever is smaller"),
+            Step::Drop(size) => write!(f, "without the {0}x{0} entries", size)
+        }
+    }
+}
+
+/// Escalates the encoding of an `ico` file until it fits in `budget` bytes.
+pub fn ico(
+    entries: Entries<encode::ico::Key>,
+    config: IcoConfig,
+    budget: usize,
+    out: &Output
+) -> Result<(Entries<encode::ico::Key>, IcoConfig), Error> {
+    let mut steps = vec![Step::Optimize, Step::Smallest];
+    steps.extend(drops(&entries, &[]));
+
+    fit((entries, config), &steps, budget, out, |(entries, config), step| match step {
+        Step::Optimize => replace(&mut config.optimize, Some(optimize::MAX_LEVEL)),
+        Step::Smallest => replace(&mut config.smallest, true),
+        Step::Drop(size) => without(entries, size),
+        Step::Quantize(_) => false
+    }, |(entries, config)| {
+        let mut data = Vec::new();
+        super::ico(entries.clone(), config)?.write(&mut data).map_err(|err| Error::Output(err, out.clone()))?;
+
+        Ok(data.len())
+    })
+}
+
+/// Escalates the encoding of an `icns` file until it fits in `budget` bytes.
+pub fn icns(
+    entries: Entries<encode::icns::Key>,
+    config: IcnsConfig,
+    budget: usize,
+    out: &Output
+) -> Result<(Entries<encode::icns::Key>, IcnsConfig), Error> {
+    let mut steps = vec![Step::Optimize];
+    steps.extend(drops(&entries, &[]));
+
+    fit((entries, config), &steps, budget, out, |(entries, config), step| match step {
+        Step::Optimize => replace(&mut config.optimize, Some(optimize::MAX_LEVEL)),
+        Step::Drop(size) => without(entries, size),
+        Step::Quantize(_) | Step::Smallest => false
+    }, |(entries, config)| {
+        let mut data = Vec::new();
+        super::icns(entries.clone(), config)?.write(&mut data).map_err(|err| Error::Output(err, out.clone()))?;
+
+        Ok(data.len())
+    })
+}
+
+/// Escalates the encoding of a favicon until every file it's saved as, including its
+/// helpers and its animated and dark variants, fits in `budget` bytes. Each attempt is
+/// saved to a temporary folder, so that the output is only written once. The sizes
+/// required by `--apple-touch` and `--web-app` are never dropped.
+pub fn favicon(
+    entries: Entries<<Favicon as Icon>::Key>,
+    config: FaviconConfig,
+    budget: usize,
+    out: &Output
+) -> Result<(Entries<<Favicon as Icon>::Key>, FaviconConfig), Error> {
+    let mut steps = vec![Step::Optimize];
+    steps.extend(QUALITIES.iter().map(|&quality| Step::Quantize(quality)));
+
+    let mut keep = Vec::new();
+    if config.apple_touch { keep.extend_from_slice(&APPLE_TOUCH_SIZES); }
+    if config.web_app { keep.extend_from_slice(&WEB_APP_SIZES); }
+    steps.extend(drops(&entries, &keep));
+
+    let temp = env::temp_dir().join(format!("icon-pie-{}", process::id()));
+
+    let result = fit((entries, config), &steps, budget, out, |(entries, config), step| match step {
+        Step::Optimize => replace(&mut config.optimize, Some(optimize::MAX_LEVEL)),
+        // Qualities above the one specified would be less aggressive than it
+        Step::Quantize(quality) => match config.quantize {
+            Some(specified) if specified <= quality => false,
+            _ => replace(&mut config.quantize, Some(quality))
+        },
+        Step::Drop(size) => without(entries, size),
+        Step::Smallest => false
+    }, |(entries, config)| {
+        let _ = fs::remove_dir_all(&temp);
+        save_favicon(entries.clone(), config, &temp)?;
+
+        size(&temp).map_err(|err| Error::Output(err, out.clone()))
+    });
+
+    let _ = fs::remove_dir_all(&temp);
+    result
+}
+
+/// Applies `steps` in turn to `state` until the size reported by `measure` is within
+/// `budget`. Steps that don't change `state` are skipped. Fails with a report of
+/// every attempt if the budget can't be met.
+fn fit<T, A, M>(
+    mut state: T,
+    steps: &[Step],
+    budget: usize,
+    out: &Output,
+    mut apply: A,
+    mut measure: M
+) -> Result<T, Error>
+where
+    A: FnMut(&mut T, Step) -> bool,
+    M: FnMut(&T) -> Result<usize, Error>
+{
+    let mut size = measure(&state)?;
+    let mut attempts = vec![(String::from("as specified"), size)];
+
+    for &step in steps {
+        if size <= budget { break; }
+        if !apply(&mut state, step) { continue; }
+
+        size = measure(&state)?;
+        attempts.push((step.to_string(), size));
+    }
+
+    if size > budget {
+        return Err(Error::OverBudget(budget, attempts));
+    }
+
+    // The icon itself is written to the standard output
+    if let Output::Path(_) = out {
+        let steps: Vec<String> = attempts.into_iter().skip(1).map(|(step, _)| step).collect();
+
+        println!(
+            "{} {} of {} bytes used, {}.",
+            style("[Budget]").with(Color::Green),
+            size,
+            budget,
+            if steps.is_empty() { String::from("as specified") } else { format!("with {}", steps.join(", ")) }
+        );
+    }
+
+    Ok(state)
+}
+
+/// The sizes of `entries` from the largest down, except for the smallest one and those
+/// in `keep`, which are never dropped.
+fn drops<K: AsSize>(entries: &Entries<K>, keep: &[u32]) -> Vec<Step> {
+    let mut sizes: Vec<u32> = entries.iter().map(|(key, _, _)| key.as_size()).collect();
+    sizes.sort_by(|a, b| b.cmp(a));
+    sizes.dedup();
+    sizes.pop();
+
+    sizes.into_iter().filter(|size| !keep.contains(size)).map(Step::Drop).collect()
+}
+
+/// Drops the entries of `size`.
+fn without<K: AsSize>(entries: &mut Entries<K>, size: u32) -> bool {
+    entries.retain(|(key, _, _)| key.as_size() != size);
+    true
+}
+
+/// Sets `field` to `value`, returning whether it changed.
+fn replace<T: PartialEq>(field: &mut T, value: T) -> bool {
+    if *field == value { return false; }
+
+    *field = value;
+    true
+}
+
+/// The total size of the files under `path`, in every subfolder.
+fn size(path: &Path) -> io::Result<usize> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        size += if entry.file_type()?.is_dir() {
+            self::size(&entry.path())?
+        } else {
+            entry.metadata()?.len() as usize
+        };
+    }
+
+    Ok(size)
+}
\ No newline at end of file
@@ -1,22 +1,59 @@
-use crate::{error::{Error, FileError}, Entries, Output};
-use std::{io::stdout, collections::HashMap};
-use icon_baker::{ico::Ico, icns::Icns, favicon::Favicon, Icon, SourceImage};
+use crate::{
+    error::{Error, FileError}, encode::{self, Encode}, compose, color, dark::{self, Dark}, animation,
+    Entries, Output, Source
+};
+use std::{io::{self, stdout}, path::{Path, PathBuf}, collections::{BTreeMap, btree_map::Entry}};
+use icon_baker::{favicon::Favicon, Icon, AsSize, SourceImage, image::DynamicImage};
 use crossterm::{style, Color};
 
+mod budget;
+
 pub enum Command {
     Help,
     Version,
-    Ico(Entries<<Ico as Icon>::Key>, Output),
-    Icns(Entries<<Icns as Icon>::Key>, Output),
+    Ico(Entries<encode::ico::Key>, IcoConfig, Output),
+    Icns(Entries<encode::icns::Key>, IcnsConfig, Output),
     Favicon(Entries<<Favicon as Icon>::Key>, FaviconConfig, Output)
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IcoConfig {
+    bmp_below: u32,
+    dither: bool,
+    optimize: Option<u8>,
+    max_bytes: Option<usize>,
+    /// Set by `--max-bytes` only.
+    smallest: bool
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IcnsConfig {
+    dither: bool,
+    macos_style: bool,
+    color_space: color::ColorSpace,
+    optimize: Option<u8>,
+    max_bytes: Option<usize>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FaviconConfig {
     apple_touch: bool,
-    web_app: bool
+    web_app: bool,
+    dark: Option<Dark>,
+    animated: bool,
+    optimize: Option<u8>,
+    quantize: Option<u8>,
+    max_bytes: Option<usize>
 }
 
+/// The number of favicon entries quantized, the number of entries in total and the
+/// number of bytes saved.
+type Quantized = (usize, usize, usize);
+
+/// The folder `icon_baker` saves the `png` entries of favicons in, relative to the
+/// output folder.
+const FAVICON_FOLDER: &str = "icons";
+
 const VERSION: &str = "0.1.4-beta";
 const TITLE: &str = r"
  _____               ______ _      
@@ -26,21 +63,45 @@ const TITLE: &str = r"
  _| || (_| (_) | | | | |   | |  __/
  \___/\___\___/|_| |_\_|   |_|\___|";
 const USAGE: [&str;5] = [
-    "icon-pie icns ((-e | --entry) <file path> <size>... [(-r | --resample) (nearest | linear | cubic)])... [(-o | --output) <path>]",
-    "icon-pie ico ((-e | --entry) <file path> <size>... [(-r | --resample) (nearest | linear | cubic)])... [(-o | --output) <path>]",
-    "icon-pie favicon ((-e | --entry) <file path> <size>... [(-r | --resample) (nearest | linear | cubic)])... [--apple-touch] [--web-app] [(-o | --output) <path>]",
+    "icon-pie icns ((-e | --entry) (<file path>... | text:<text> | emoji:<emoji> [--font <file path>] [--text-color <color>]) <size>... [(-r | --resample) (nearest | linear | cubic | lanczos2 | lanczos3 | mitchell | catmull-rom)] [--linear-light] [--pixel-art [snap] [scale2x]] [--sharpen [<amount>,<radius>]] [--trim [<threshold>]] [--padding (<percent>% | <pixels>)] [--fit (contain | cover | fill)] [--align <alignment>] [--layer <file path> [(-r | --resample) <filter>] [--offset <x>,<y>] [--scale <scale>] [--opacity <opacity>]]... [--monochrome [alpha | threshold:<threshold> | luminance] [<color>]] [--background (<color> | <color>,<color> | <file path>)] [--shape (rounded:<radius> | continuous:<radius> | circle | squircle)] [--depth (1 | 4 | 8 | 32)])... [--dither] [--macos-style] [--color-space (srgb | p3)] [--optimize (1 | 2 | 3)] [--max-bytes <bytes>] [--badge <text> [--badge-style (ribbon | corner)] [--badge-color <color>] [--badge-text-color <color>] [--badge-min-size <size>]] [(-o | --output) <path>]",
+    "icon-pie ico ((-e | --entry) (<file path>... | text:<text> | emoji:<emoji> [--font <file path>] [--text-color <color>]) <size>... [(-r | --resample) (nearest | linear | cubic | lanczos2 | lanczos3 | mitchell | catmull-rom)] [--linear-light] [--pixel-art [snap] [scale2x]] [--sharpen [<amount>,<radius>]] [--trim [<threshold>]] [--padding (<percent>% | <pixels>)] [--fit (contain | cover | fill)] [--align <alignment>] [--layer <file path> [(-r | --resample) <filter>] [--offset <x>,<y>] [--scale <scale>] [--opacity <opacity>]]... [--monochrome [alpha | threshold:<threshold> | luminance] [<color>]] [--background (<color> | <color>,<color> | <file path>)] [--shape (rounded:<radius> | continuous:<radius> | circle | squircle)] [--depth (1 | 4 | 8 | 32)])... [--bmp-below <size>] [--dither] [--color-space srgb] [--optimize (1 | 2 | 3)] [--max-bytes <bytes>] [--badge <text> [--badge-style (ribbon | corner)] [--badge-color <color>] [--badge-text-color <color>] [--badge-min-size <size>]] [(-o | --output) <path>]",
+    "icon-pie favicon ((-e | --entry) (<file path>... | text:<text> | emoji:<emoji> [--font <file path>] [--text-color <color>]) <size>... [(-r | --resample) (nearest | linear | cubic | lanczos2 | lanczos3 | mitchell | catmull-rom)] [--linear-light] [--pixel-art [snap] [scale2x]] [--sharpen [<amount>,<radius>]] [--trim [<threshold>]] [--padding (<percent>% | <pixels>)] [--fit (contain | cover | fill)] [--align <alignment>] [--layer <file path> [(-r | --resample) <filter>] [--offset <x>,<y>] [--scale <scale>] [--opacity <opacity>]]... [--monochrome [alpha | threshold:<threshold> | luminance] [<color>]] [--background (<color> | <color>,<color> | <file path>)] [--shape (rounded:<radius> | continuous:<radius> | circle | squircle)])... [--apple-touch] [--web-app] [--dark (<file path> | invert)] [--animated] [--color-space srgb] [--optimize (1 | 2 | 3)] [--quantize <quality>] [--max-bytes <bytes>] [--badge <text> [--badge-style (ribbon | corner)] [--badge-color <color>] [--badge-text-color <color>] [--badge-min-size <size>]] [(-o | --output) <path>]",
     "icon-pie (-h | --help)",
     "icon-pie (-v | --version)"
 ];
 
-const OPTIONS: [&str;7] = [
-    "Specify an entry's source image and target sizes.",
-    "Specify a re-sampling filter: `nearest`, `linear` or `cubic`. If no filter is specified the app defaults to `nearest`.",
-    "Specify an output path. This is optional. If absent the output is directed to `stdout`.",
-    "Favicon specific option. Confire the output to include link tags for apple-touch icons in the HTML helper.",
-    "Favicon specific option. Confire the output to include a `.webmanifest` helper for PWA icons.",
-    "Help.",
-    "Display version information.",
+const OPTIONS: [(&str, &str);31] = [
+    ("-e (FILE)... (SIZE)..., --entry (FILE)... (SIZE)... ", "Specify an entry's source images and target sizes. Icns retina entries are specified as `SIZE@2x`. Given multiple sources, each size is drawn from the one whose size tag, as in `logo-16.svg`, is the closest not smaller than it. Untagged sources are used for larger sizes. The options of an entry may follow its sizes in any order."),
+    ("-e text:TEXT (SIZE)..., -e emoji:EMOJI (SIZE)...    ", "Use a short text or an emoji as an entry's source, rendered directly at each of its sizes. Text is drawn with a built-in bitmap font unless `--font` is specified, while emoji require it."),
+    ("--font FILE, --text-color COLOR                     ", "Glyph specific options. Specify a TrueType or OpenType font and a hex color for a text or emoji source. Fonts whose emoji have no outlines, such as color bitmap fonts, aren't supported."),
+    ("-r FILTER, --resample FILTER                        ", "Specify a re-sampling filter: `nearest`, `linear`, `cubic`, `lanczos2`, `lanczos3`, `mitchell` or `catmull-rom`. `cubic` is Keys' cubic convolution with `a = -0.75`, slightly sharper than `catmull-rom`. If no filter is specified the app defaults to `nearest`."),
+    ("--linear-light                                      ", "Blend pixels in linear light when re-sampling an entry, rather than in sRGB space. Has no effect on `nearest`."),
+    ("--pixel-art [snap] [scale2x]                        ", "Scale an entry's source by integer factors only, rejecting sizes that would down-scale it or that aren't integer multiples of it. `snap` pads such sizes instead, while `scale2x` smooths edges with the Scale2x and Scale3x algorithms."),
+    ("--sharpen [AMOUNT,RADIUS]                           ", "Apply an unsharp mask of AMOUNT and RADIUS pixels to each size of an entry after re-sampling it. If no parameters are specified only sizes smaller than `48` are sharpened, with a mild mask."),
+    ("--trim [THRESHOLD]                                  ", "Crop an entry's source image to the bounds of the pixels whose alpha exceeds THRESHOLD before fitting it. If no threshold is specified the app defaults to `0`."),
+    ("--padding (PERCENT% | PIXELS)                       ", "Leave a transparent margin on each side of an entry, as a percentage of its size, such as `10%`, or in pixels."),
+    ("--fit FIT                                           ", "Specify how an entry's source image is fit into a square: `contain` (the default) scales it to fit inside it, `cover` crops its edges and `fill` stretches it."),
+    ("--align ALIGNMENT                                   ", "Specify the edges an entry's source image is aligned to, when it isn't square: `center` (the default), `top`, `bottom`, `left`, `right`, `top-left`, `top-right`, `bottom-left` or `bottom-right`."),
+    ("--layer FILE [OPTIONS]                              ", "Stack an image on top of an entry. Layers accept `-r FILTER`, `--offset X,Y` (from the center, as percentages of the entry's size), `--scale SCALE` and `--opacity OPACITY` (from `0` to `1`). Each layer is rendered directly at its final size."),
+    ("--monochrome [MODE] [COLOR]                         ", "Reduce an entry, along with its layers, to a silhouette of COLOR (defaults to `#000000`). MODE is `alpha` (the default), which preserves its alpha channel, `threshold:THRESHOLD`, or `luminance`, which makes dark pixels opaque and light ones transparent."),
+    ("--background BACKGROUND                             ", "Place an entry on top of a hex color, such as `#1e90ff`, a vertical gradient between two colors, such as `#1e90ff,#000080`, or an image, scaled to cover the entry."),
+    ("--shape SHAPE                                       ", "Clip an entry to a shape: `rounded:RADIUS` or `continuous:RADIUS`, with a corner radius given as a percentage of its size, `circle` or `squircle`."),
+    ("--depth DEPTH                                       ", "Ico and icns specific option. Specify the color depth of an entry: `1`, `4`, `8` or `32`. If no depth is specified the app defaults to `32`."),
+    ("-o PATH, --output PATH                              ", "Specify an output path. This is optional. If absent the output is directed to `stdout`."),
+    ("--bmp-below SIZE                                    ", "Ico specific option. Store 32-bit entries smaller than SIZE as `bmp` and all others as `png`. Defaults to `256`."),
+    ("--dither                                            ", "Ico and icns specific option. Apply dithering to entries with a color depth of `1`, `4` or `8`."),
+    ("--dark (FILE | invert)                              ", "Favicon specific option. Include a dark variant of every entry, rendered from FILE or by inverting the luminance of the entry, and link it from the HTML helper with a `prefers-color-scheme` media query, as well as from the `.webmanifest` helper with `--web-app`. Inversion is applied after compositing."),
+    ("--animated                                          ", "Favicon specific option. Render every frame of entries sourced from animated `gif` files and link the resulting animated `gif` favicons from the HTML helper, after their static `png` fallbacks."),
+    ("--color-space (srgb | p3)                           ", "Specify the color space sources are converted to, according to their embedded ICC profiles. `p3` is icns specific, and converts `png` entries to Display P3 and tags them with its profile. Other entries remain sRGB. Defaults to `srgb`."),
+    ("--optimize LEVEL                                    ", "Losslessly optimize every `png` encoded entry before it's written, reporting the bytes saved. Level `1` recompresses entries, `2` also reduces their color types and bit depths, converting them to palettes where possible, and `3` also tries every filter type. The `png` entries of ico files are kept in 8-bit RGBA. Requires an output path for favicons."),
+    ("--quantize QUALITY                                  ", "Favicon specific option. Reduce every entry to a palette of at most 256 colors with alpha. Entries that fall short of QUALITY, from `0` to `100` as in pngquant, or that wouldn't get any smaller, are kept in true-color. Requires an output path."),
+    ("--max-bytes BYTES                                   ", "Keep the output within BYTES, trying more aggressive encodings in turn until it fits: `--optimize 3`, then `--quantize` at decreasing qualities for favicons or the smaller of `bmp` and `png` entries for ico files, then dropping entries from the largest size down, except for the sizes `--apple-touch` and `--web-app` require. Favicons are measured as a whole, helpers included. Fails with a report of every attempt otherwise."),
+    ("--macos-style                                       ", "Icns specific option. Composite each entry into the macOS icon template: a white square with continuous corners, inset in a 824/1024 grid, casting a drop shadow."),
+    ("--badge TEXT [OPTIONS]                              ", "Overlay a text badge, such as `BETA`, on every entry. Badges accept `--badge-style` (`ribbon` or `corner`), `--badge-color COLOR`, `--badge-text-color COLOR` and `--badge-min-size SIZE`, below which entries are left without a badge (defaults to `32`)."),
+    ("--apple-touch                                       ", "Favicon specific option. Confire the output to include link tags for apple-touch icons in the HTML helper."),
+    ("--web-app                                           ", "Favicon specific option. Confire the output to include a `.webmanifest` helper for PWA icons."),
+    ("-h, --help                                          ", "Help."),
+    ("-v, --version                                       ", "Display version information."),
 ];
 
 const EXAMPLES: [&str;3] = [
@@ -52,13 +113,63 @@ const EXAMPLES: [&str;3] = [
 impl Command {
     pub fn eval(self) -> Result<(), Error> {
         match self {
-            Command::Icns(entries, out) => write(&mut icon::<Icns>(entries)?, out)?,
-            Command::Ico(entries, out) => write(&mut icon::<Ico>(entries)?, out)?,
+            Command::Icns(entries, config, out) => {
+                let (entries, config) = match config.max_bytes {
+                    Some(budget) => budget::icns(entries, config, budget, &out)?,
+                    None => (entries, config)
+                };
+
+                let mut icon = icns(entries, &config)?;
+                write(&mut icon, out.clone())?;
+
+                if config.optimize.is_some() {
+                    saved(icon.saved(), &out);
+                }
+            },
+            Command::Ico(entries, config, out) => {
+                let (entries, config) = match config.max_bytes {
+                    Some(budget) => budget::ico(entries, config, budget, &out)?,
+                    None => (entries, config)
+                };
+
+                let mut icon = ico(entries, &config)?;
+                write(&mut icon, out.clone())?;
+
+                if config.optimize.is_some() {
+                    saved(icon.saved(), &out);
+                }
+            },
             Command::Favicon(entries, config, out) => {
-                write(
-                    icon::<Favicon>(entries)?.apple_touch(config.apple_touch).web_app(config.web_app),
-                    out
-                )?
+                let (entries, config) = match config.max_bytes {
+                    Some(budget) => budget::favicon(entries, config, budget, &out)?,
+                    None => (entries, config)
+                };
+
+                let path = match &out {
+                    Output::Path(path) => path,
+                    Output::Stdout => return write(&mut favicon(entries, &config)?, out)
+                };
+
+                let (quantized, optimized) = save_favicon(entries, &config, path)?;
+                println!(
+                    "{} Icon saved at {}.",
+                    style("[Success]").with(Color::Green),
+                    style(path.display()).with(Color::Blue)
+                );
+
+                if let Some((quantized, total, saved)) = quantized {
+                    println!(
+                        "{} Reduced {} of {} entries to palettes, saving {} bytes.",
+                        style("[Quantized]").with(Color::Green),
+                        quantized,
+                        total,
+                        saved
+                    );
+                }
+
+                if let Some(total) = optimized {
+                    saved(total, &out);
+                }
             },
             Command::Help => help(),
             Command::Version => version()
@@ -68,30 +179,209 @@ impl Command {
     }
 }
 
+impl IcoConfig {
+    pub fn new(bmp_below: u32, dither: bool, optimize: Option<u8>, max_bytes: Option<usize>) -> Self {
+        IcoConfig { bmp_below, dither, optimize, max_bytes, smallest: false }
+    }
+}
+
+impl IcnsConfig {
+    pub fn new(
+        dither: bool,
+        macos_style: bool,
+        color_space: color::ColorSpace,
+        optimize: Option<u8>,
+        max_bytes: Option<usize>
+    ) -> Self {
+        IcnsConfig { dither, macos_style, color_space, optimize, max_bytes }
+    }
+}
+
 impl FaviconConfig {
-    pub fn new(web_app: bool, apple_touch: bool) -> Self {
-        FaviconConfig { web_app, apple_touch }
+    pub fn new(
+        web_app: bool,
+        apple_touch: bool,
+        dark: Option<Dark>,
+        animated: bool,
+        optimize: Option<u8>,
+        quantize: Option<u8>,
+        max_bytes: Option<usize>
+    ) -> Self {
+        FaviconConfig { web_app, apple_touch, dark, animated, optimize, quantize, max_bytes }
     }
 }
 
 /// Trys to create an `I` from an `Entries<I::Key>`.
 fn icon<I: Icon>(entries: Entries<I::Key>) -> Result<I, Error> {
     let mut icon = I::with_capacity(entries.len());
-    let mut source_map = HashMap::with_capacity(entries.len());
 
-    for (key, path, filter) in entries {
-        let src = source_map.entry(path.clone())
-            .or_insert(SourceImage::open(&path).map_err(|err| FileError(err, path.clone()))?);
+    add_entries(entries, |key, src, render| icon.add_entry(render, src, key))?;
+
+    Ok(icon)
+}
+
+/// Trys to create a `Favicon` from an `Entries<FaviconKey>`, configured by `config`.
+fn favicon(entries: Entries<<Favicon as Icon>::Key>, config: &FaviconConfig) -> Result<Favicon, Error> {
+    let mut icon = icon::<Favicon>(entries)?;
+    icon.apple_touch(config.apple_touch).web_app(config.web_app);
+
+    Ok(icon)
+}
+
+/// Trys to create an `encode::Ico` from an `Entries<encode::ico::Key>`, configured by
+/// `config`.
+fn ico(entries: Entries<encode::ico::Key>, config: &IcoConfig) -> Result<encode::Ico, Error> {
+    let mut icon = encode::Ico::with_capacity(entries.len());
+
+    add_entries(entries, |key, src, render| icon.add_entry(render, src, key))?;
+
+    icon.bmp_below(config.bmp_below)
+        .smallest(config.smallest)
+        .dither(config.dither)
+        .optimize(config.optimize);
+
+    Ok(icon)
+}
+
+/// Trys to create an `encode::Icns` from an `Entries<encode::icns::Key>`, configured by
+/// `config`. If `macos_style` is set the entries are composited into the macOS icon
+/// template.
+fn icns(entries: Entries<encode::icns::Key>, config: &IcnsConfig) -> Result<encode::Icns, Error> {
+    let mut icon = encode::Icns::with_capacity(entries.len());
+    let macos_style = config.macos_style;
+    icon.dither(config.dither).color_space(config.color_space).optimize(config.optimize);
+
+    add_entries(entries, |key, src, render| {
+        icon.add_entry(|src, size| if macos_style {
+            compose::macos_style(|size| render(src, size), size)
+        } else {
+            render(src, size)
+        }, src, key)
+    })?;
+
+    Ok(icon)
+}
+
+/// Saves the favicon of `entries` at `path`, along with its animated and dark variants,
+/// and then quantizes and optimizes its `png` entries. Returns the results of
+/// `process_favicon`.
+fn save_favicon(
+    entries: Entries<<Favicon as Icon>::Key>,
+    config: &FaviconConfig,
+    path: &Path
+) -> Result<(Option<Quantized>, Option<usize>), Error> {
+    let out = || Output::Path(path.to_path_buf());
+    let dark = config.dark.as_ref().map(|dark| dark::entries(&entries, dark));
+    let animated = if config.animated { Some(entries.clone()) } else { None };
+
+    Encode::save(&mut favicon(entries, config)?, &path).map_err(|err| Error::Output(err, out()))?;
+
+    if let Some(entries) = animated {
+        animation::save(&entries, path).map_err(|err| Error::Output(err, out()))?;
+    }
+
+    if let Some(entries) = dark {
+        Encode::save(&mut favicon(entries, config)?, &path.join(dark::FOLDER))
+            .map_err(|err| Error::Output(err, out()))?;
+        dark::link(path).map_err(|err| Error::Output(err, out()))?;
+    }
+
+    process_favicon(path, config).map_err(|err| Error::Output(err, out()))
+}
+
+/// The folders holding the `png` entries of the favicon saved at `path`, along with
+/// those of its dark variant.
+fn favicon_folders(path: &Path, config: &FaviconConfig) -> Vec<PathBuf> {
+    let mut folders = vec![path.join(FAVICON_FOLDER)];
+    if config.dark.is_some() {
+        folders.push(path.join(dark::FOLDER).join(FAVICON_FOLDER));
+    }
+
+    folders
+}
+
+/// Quantizes and then optimizes the `png` entries of the favicon saved at `path`, as
+/// specified by `config`. Returns the results of `quantize::folder` and
+/// `optimize::folder`, summed over every folder.
+fn process_favicon(
+    path: &Path,
+    config: &FaviconConfig
+) -> io::Result<(Option<Quantized>, Option<usize>)> {
+    let folders = favicon_folders(path, config);
+
+    let quantized = match config.quantize {
+        Some(quality) => {
+            let mut sum = (0, 0, 0);
+            for folder in &folders {
+                let (quantized, total, saved) = encode::quantize::folder(folder, quality)?;
+                sum = (sum.0 + quantized, sum.1 + total, sum.2 + saved);
+            }
 
-        if let Err(err) = icon.add_entry(|src, size| filter.call(src, size), src, key) {
-            return Err(Error::from_baker(err, path.clone()));
+            Some(sum)
+        },
+        None => None
+    };
+
+    let optimized = match config.optimize {
+        Some(level) => {
+            let mut saved = 0;
+            for folder in &folders {
+                saved += encode::optimize::folder(folder, level)?;
+            }
+
+            Some(saved)
+        },
+        None => None
+    };
+
+    Ok((quantized, optimized))
+}
+
+/// Opens the source image of each entry and hands it to `adder`, along with the
+/// function rendering the entry. Sources shared between multiple entries are only
+/// opened once.
+fn add_entries<K, F>(entries: Entries<K>, mut adder: F) -> Result<(), Error>
+where
+    K: AsSize,
+    F: FnMut(
+        K,
+        &SourceImage,
+        &dyn Fn(&SourceImage, u32) -> io::Result<DynamicImage>
+    ) -> Result<(), icon_baker::Error<K>>
+{
+    // Sources are kept in a `BTreeMap` rather than a `HashMap`, whose randomized order
+    // would make anything iterating over them vary between runs
+    let mut source_map = BTreeMap::new();
+
+    // Glyphs are rendered from scratch, so they don't have a source image
+    let placeholder = SourceImage::from(DynamicImage::new_rgba8(1, 1));
+
+    for (key, source, config) in entries {
+        let result = match &source {
+            Source::Path(path) => {
+                let src = match source_map.entry(path.clone()) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(
+                        color::open(path, config.color_space).map_err(|err| FileError(err, path.clone()))?
+                    )
+                };
+
+                adder(key, src, &|src, size| config.render(src, size))
+            },
+            Source::Glyph(glyph) => {
+                adder(key, &placeholder, &|_, size| config.render_glyph(glyph, size))
+            }
+        };
+
+        if let Err(err) = result {
+            return Err(Error::from_baker(err, source.path()));
         }
     }
 
-    Ok(icon)
+    Ok(())
 }
 
-fn write<I: Icon>(icon: &mut I, output: Output) -> Result<(), Error> {
+fn write<I: Encode>(icon: &mut I, output: Output) -> Result<(), Error> {
     match &output {
         Output::Path(path) => {
             icon.save(path)
@@ -110,6 +400,14 @@ fn write<I: Icon>(icon: &mut I, output: Output) -> Result<(), Error> {
     }
 }
 
+/// Reports the number of bytes saved by `--optimize`, unless the icon was written to
+/// the standard output.
+fn saved(saved: usize, output: &Output) {
+    if let Output::Path(_) = output {
+        println!("{} Saved {} bytes.", style("[Optimized]").with(Color::Green), saved);
+    }
+}
+
 #[inline]
 fn help() {
     println!(
@@ -129,24 +427,10 @@ fn help() {
         style(USAGE[4]).with(Color::Green),
     );
 
-    println!(
-        "\n{}\n   {}{}\n   {}{}\n   {}{}\n   {}{}\n   {}{}\n   {}{}\n   {}{}",
-        style("Options:").with(Color::Blue),
-        style("-e FILE (SIZE)..., --entry FILE (SIZE)... ").with(Color::Green),
-        OPTIONS[0],
-        style("-r FILTER, --resample FILTER              ").with(Color::Green),
-        OPTIONS[1],
-        style("-o PATH, --output PATH                    ").with(Color::Green),
-        OPTIONS[2],
-        style("--apple-touch                             ").with(Color::Green),
-        OPTIONS[3],
-        style("--web-app                                 ").with(Color::Green),
-        OPTIONS[4],
-        style("-h, --help                                ").with(Color::Green),
-        OPTIONS[5],
-        style("-v, --version                             ").with(Color::Green),
-        OPTIONS[6]
-    );
+    println!("\n{}", style("Options:").with(Color::Blue));
+    for (option, description) in OPTIONS.iter() {
+        println!("   {}{}", style(option).with(Color::Green), description);
+    }
 
     println!(
         "\n{}\n   {}\n   {}\n   {}\n",
@@ -160,4 +444,130 @@ fn help() {
 #[inline]
 fn version() {
     println!("icon-pie v{}", VERSION);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use std::{env, fs, process};
+    use icon_baker::image::{RgbaImage, Rgba};
+
+    /// A temporary folder holding the sources of the tests: `big.png`, a _64x64_
+    /// gradient with a transparent corner, and `small.png`, a _16x16_ checkerboard.
+    fn sources(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("icon-pie-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, if x + y < 16 { 0 } else { 0xFF }])
+        }).save(path.join("big.png")).unwrap();
+
+        RgbaImage::from_fn(16, 16, |x, y| {
+            if (x + y) % 2 == 0 { Rgba([0xFF, 0x80, 0, 0xFF]) } else { Rgba([0, 0x80, 0xFF, 0x80]) }
+        }).save(path.join("small.png")).unwrap();
+
+        path
+    }
+
+    /// Evaluates the command `args`, in which sources and outputs are relative to
+    /// `path`.
+    fn eval(path: &Path, args: &[&str]) {
+        let args = args.iter()
+            .map(|arg| if arg.contains('.') || arg.starts_with("out") {
+                path.join(arg).display().to_string()
+            } else {
+                String::from(*arg)
+            })
+            .collect();
+
+        match parse::parse(args) {
+            Ok(command) => command.eval().unwrap(),
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    /// The contents of every file in the folder at `path`, by their relative paths.
+    fn contents(path: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut files = BTreeMap::new();
+
+        for entry in fs::read_dir(path).unwrap() {
+            let entry = entry.unwrap().path();
+            let name = PathBuf::from(entry.file_name().unwrap());
+
+            if entry.is_dir() {
+                for (file, data) in contents(&entry) {
+                    files.insert(name.join(file), data);
+                }
+            } else {
+                files.insert(name, fs::read(&entry).unwrap());
+            }
+        }
+
+        files
+    }
+
+    #[test]
+    fn ico_is_deterministic() {
+        let path = sources("ico");
+        // Sources are shared between entries, so that they're looked up in the cache
+        let args = [
+            "-e", "small.png", "16", "--depth", "4",
+            "-e", "big.png", "32", "48", "64", "-r", "cubic",
+            "-e", "small.png", "big.png", "24",
+            "--bmp-below", "48", "--optimize", "3"
+        ];
+
+        for out in &["out-1.ico", "out-2.ico"] {
+            eval(&path, &[&["ico"], &args[..], &["-o", out]].concat());
+        }
+
+        assert_eq!(fs::read(path.join("out-1.ico")).unwrap(), fs::read(path.join("out-2.ico")).unwrap());
+        let _ = fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn icns_is_deterministic() {
+        let path = sources("icns");
+
+        // Entries in a different order make for the same file, as chunks are sorted
+        eval(&path, &["icns", "-e", "small.png", "16", "-e", "big.png", "32", "16@2x", "64", "-o", "out-1.icns"]);
+        eval(&path, &["icns", "-e", "big.png", "64", "16@2x", "32", "-e", "small.png", "16", "-o", "out-2.icns"]);
+
+        let data = fs::read(path.join("out-1.icns")).unwrap();
+        assert_eq!(data, fs::read(path.join("out-2.icns")).unwrap());
+
+        let mut ostypes = Vec::new();
+        let mut i = 8;
+        while i + 8 <= data.len() {
+            ostypes.push(data[i..i + 4].to_vec());
+            i += u32::from_be_bytes([data[i + 4], data[i + 5], data[i + 6], data[i + 7]]) as usize;
+        }
+
+        assert_eq!(i, data.len());
+        assert!(ostypes.len() > 1);
+        assert!(ostypes.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let _ = fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn quantized_favicon_is_deterministic() {
+        let path = sources("favicon");
+        let args = ["-e", "small.png", "16", "-e", "big.png", "32", "64", "-r", "linear"];
+
+        eval(&path, &[&["favicon"], &args[..], &["-o", "out-0"]].concat());
+        for out in &["out-1", "out-2"] {
+            eval(&path, &[&["favicon"], &args[..], &["--quantize", "50", "-o", out]].concat());
+        }
+
+        let first = contents(&path.join("out-1"));
+        assert!(first.keys().any(|file| file.starts_with(FAVICON_FOLDER)));
+        assert_ne!(first, contents(&path.join("out-0")));
+        assert_eq!(first, contents(&path.join("out-2")));
+
+        let _ = fs::remove_dir_all(&path);
+    }
 }
\ No newline at end of file
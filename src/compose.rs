@@ -0,0 +1,261 @@
+use crate::{sample, color::{self, ColorSpace}, ResamplingFilter, layout::{Layout, Fit}};
+use std::{io, path::PathBuf};
+use icon_baker::{
+    SourceImage,
+    image::{DynamicImage, RgbaImage, Rgba}
+};
+
+/// The number of samples taken along each axis of a pixel when computing the
+/// coverage of a shape mask.
+const SUBSAMPLES: u32 = 4;
+
+/// The dimensions of the macOS icon template, in a _1024x1024_ grid.
+mod macos {
+    pub const GRID: f32 = 1024.0;
+    pub const BODY: f32 = 824.0;
+    /// The corner radius of the body, as a percentage of its size.
+    pub const RADIUS: f32 = 22.5;
+    pub const SHADOW_BLUR: f32 = 28.0;
+    pub const SHADOW_OFFSET: f32 = 12.0;
+    pub const SHADOW_OPACITY: f32 = 0.5;
+}
+
+/// What an entry is placed on top of.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Background {
+    Color(Rgba<u8>),
+    /// A vertical gradient, from the top color to the bottom color.
+    Gradient(Rgba<u8>, Rgba<u8>),
+    /// An image, scaled to cover the entry.
+    Image(PathBuf)
+}
+
+/// The shape an entry is clipped to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// A square with rounded corners, of a radius given as a percentage of the
+    /// size of the entry.
+    Rounded(f32),
+    Circle,
+    /// A superellipse of exponent `4`.
+    Squircle,
+    /// A square with continuous corners, of a radius given as a percentage of the
+    /// size of the entry. The corners are approximated by quarters of superellipses.
+    Continuous(f32)
+}
+
+/// An image stacked on top of an entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub path: PathBuf,
+    pub filter: ResamplingFilter,
+    /// The offset of the center of the layer from the center of the entry, as
+    /// percentages of the size of the entry.
+    pub offset: (f32, f32),
+    /// The size of the layer relative to the size of the entry.
+    pub scale: f32,
+    pub opacity: f32
+}
+
+impl Background {
+    /// Renders the background of an entry of _`size`x`size`_, using `filter` to
+    /// resample background images, which are converted to `color_space`.
+    pub fn render<F: FnMut(&SourceImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        size: u32,
+        color_space: ColorSpace,
+        filter: F
+    ) -> io::Result<RgbaImage> {
+        match self {
+            Background::Color(color) => Ok(RgbaImage::from_pixel(size, size, *color)),
+            Background::Gradient(top, bottom) => Ok(RgbaImage::from_fn(size, size, |_, y| {
+                lerp(*top, *bottom, (y as f32 + 0.5) / size as f32)
+            })),
+            Background::Image(path) => {
+                let layout = Layout { fit: Fit::Cover, ..Layout::default() };
+                Ok(layout.render(&color::open(path, color_space)?, size, filter)?.to_rgba())
+            }
+        }
+    }
+}
+
+impl Shape {
+    /// Whether the point `(x, y)` lies inside the shape, for an entry of
+    /// _`size`x`size`_.
+    fn contains(self, x: f32, y: f32, size: f32) -> bool {
+        let half = size / 2.0;
+        let (u, v) = ((x - half).abs(), (y - half).abs());
+
+        match self {
+            Shape::Rounded(percent) => {
+                let radius = (size * percent / 100.0).min(half);
+                let (du, dv) = ((u - half + radius).max(0.0), (v - half + radius).max(0.0));
+
+                du * du + dv * dv <= radius * radius
+            },
+            Shape::Continuous(percent) => {
+                // The curvature of continuous corners starts further from the edges
+                let radius = (size * percent / 100.0 * 1.5).min(half);
+                if radius == 0.0 { return true; }
+
+                let (du, dv) = ((u - half + radius).max(0.0), (v - half + radius).max(0.0));
+
+                (du / radius).powi(5) + (dv / radius).powi(5) <= 1.0
+            },
+            Shape::Circle => u * u + v * v <= half * half,
+            Shape::Squircle => (u / half).powi(4) + (v / half).powi(4) <= 1.0
+        }
+    }
+
+    /// The fraction of the pixel at `(x, y)` covered by the shape.
+    fn coverage(self, x: u32, y: u32, size: u32) -> f32 {
+        let mut inside = 0;
+
+        for i in 0..SUBSAMPLES {
+            for j in 0..SUBSAMPLES {
+                let sx = x as f32 + (i as f32 + 0.5) / SUBSAMPLES as f32;
+                let sy = y as f32 + (j as f32 + 0.5) / SUBSAMPLES as f32;
+
+                if self.contains(sx, sy, size as f32) { inside += 1; }
+            }
+        }
+
+        inside as f32 / (SUBSAMPLES * SUBSAMPLES) as f32
+    }
+}
+
+/// Places `image` on top of `background`, if any, and clips the result to `shape`,
+/// if any. The edges of the shape are anti-aliased.
+pub fn compose(image: &RgbaImage, background: Option<RgbaImage>, shape: Option<Shape>) -> RgbaImage {
+    let mut output = match background {
+        Some(mut background) => {
+            over(&mut background, image, 0, 0);
+            background
+        },
+        None => image.clone()
+    };
+
+    if let Some(shape) = shape {
+        let size = output.width();
+
+        for (x, y, px) in output.enumerate_pixels_mut() {
+            px.0[3] = (f32::from(px.0[3]) * shape.coverage(x, y, size)).round() as u8;
+        }
+    }
+
+    output
+}
+
+/// Stacks `layers` on top of `image`, in order. Each layer is converted to `color_space`
+/// and rendered at its final size, so that it's only resampled once.
+pub fn stack(
+    image: &mut RgbaImage,
+    layers: &[Layer],
+    linear_light: bool,
+    color_space: ColorSpace
+) -> io::Result<()> {
+    let size = image.width() as f32;
+
+    for layer in layers {
+        let layer_size = ((size * layer.scale).round() as u32).max(1);
+        let source = color::open(&layer.path, color_space)?;
+        let mut rendered = layer.filter.call(&source, layer_size, linear_light)?.to_rgba();
+
+        if layer.opacity < 1.0 {
+            for px in rendered.pixels_mut() {
+                px.0[3] = (f32::from(px.0[3]) * layer.opacity).round() as u8;
+            }
+        }
+
+        let x = (size - layer_size as f32) / 2.0 + layer.offset.0 * size / 100.0;
+        let y = (size - layer_size as f32) / 2.0 + layer.offset.1 * size / 100.0;
+        over(image, &rendered, x.round() as i64, y.round() as i64);
+    }
+
+    Ok(())
+}
+
+/// Renders an entry of _`size`x`size`_ in the style of macOS icons: the artwork,
+/// rendered at the size of the body of the template by `render`, is placed on a
+/// white square with continuous corners, which casts a soft drop shadow.
+pub fn macos_style<F: FnOnce(u32) -> io::Result<DynamicImage>>(
+    render: F,
+    size: u32
+) -> io::Result<DynamicImage> {
+    let scale = size as f32 / macos::GRID;
+    let body = ((macos::BODY * scale).round() as u32).max(1);
+    let offset = (size - body) / 2;
+
+    let mut artwork = RgbaImage::from_pixel(body, body, Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
+    over(&mut artwork, &render(body)?.to_rgba(), 0, 0);
+    let artwork = compose(&artwork, None, Some(Shape::Continuous(macos::RADIUS)));
+
+    // The shadow is cast by the body of the icon, offset downwards
+    let mut shadow = RgbaImage::new(size, size);
+    let shadow_offset = (macos::SHADOW_OFFSET * scale).round() as u32;
+    for (x, y, px) in artwork.enumerate_pixels() {
+        if y + offset + shadow_offset >= size { continue; }
+
+        let alpha = f32::from(px.0[3]) * macos::SHADOW_OPACITY;
+        shadow.put_pixel(x + offset, y + offset + shadow_offset, Rgba([0, 0, 0, alpha.round() as u8]));
+    }
+
+    let mut output = sample::blur(&shadow, macos::SHADOW_BLUR * scale / 2.0);
+    over(&mut output, &artwork, i64::from(offset), i64::from(offset));
+
+    Ok(DynamicImage::ImageRgba8(output))
+}
+
+/// Composites `top` over `bottom` at `(x, y)`. Pixels of `top` falling outside of
+/// `bottom` are discarded.
+fn over(bottom: &mut RgbaImage, top: &RgbaImage, x: i64, y: i64) {
+    for (tx, ty, px) in top.enumerate_pixels() {
+        let (bx, by) = (i64::from(tx) + x, i64::from(ty) + y);
+        if bx < 0 || by < 0 || bx >= i64::from(bottom.width()) || by >= i64::from(bottom.height()) {
+            continue;
+        }
+
+        blend(bottom.get_pixel_mut(bx as u32, by as u32), *px, 1.0);
+    }
+}
+
+/// Composites `color`, with its alpha scaled by `coverage`, over `px`.
+pub fn blend(px: &mut Rgba<u8>, color: Rgba<u8>, coverage: f32) {
+    let top = f32::from(color.0[3]) / 255.0 * coverage;
+    if top == 0.0 { return; }
+
+    let bottom = f32::from(px.0[3]) / 255.0;
+    let alpha = top + bottom * (1.0 - top);
+
+    for ch in 0..3 {
+        let c = f32::from(color.0[ch]) * top + f32::from(px.0[ch]) * bottom * (1.0 - top);
+        px.0[ch] = (c / alpha).round() as u8;
+    }
+
+    px.0[3] = (alpha * 255.0).round() as u8;
+}
+
+fn lerp(a: Rgba<u8>, b: Rgba<u8>, t: f32) -> Rgba<u8> {
+    let mut output = [0u8;4];
+
+    for (c, (a, b)) in output.iter_mut().zip(a.0.iter().zip(b.0.iter())) {
+        *c = (f32::from(*a) * (1.0 - t) + f32::from(*b) * t).round() as u8;
+    }
+
+    Rgba(output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_radius_is_square() {
+        for &shape in &[Shape::Rounded(0.0), Shape::Continuous(0.0)] {
+            for (x, y) in (0..16).flat_map(|x| (0..16).map(move |y| (x, y))) {
+                assert_eq!(shape.coverage(x, y, 16), 1.0, "{:?} at ({}, {})", shape, x, y);
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,97 @@
+use crate::{helper, Entries, Source};
+use std::{io, fs, path::{Path, PathBuf}};
+use icon_baker::image::RgbaImage;
+
+/// The folder dark variants are saved in, relative to the output folder.
+pub const FOLDER: &str = "dark";
+
+/// The media query qualifying the links to dark variants.
+const MEDIA: &str = "(prefers-color-scheme: dark)";
+
+/// How the dark variant of an icon is obtained.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Dark {
+    /// A second source, rendered with the options of each entry.
+    Path(PathBuf),
+    /// The luminance of each entry is inverted, preserving its hue.
+    Invert
+}
+
+/// The entries of the dark variant of an icon, each rendered from the dark source
+/// with the options of the matching entry of `entries`.
+pub fn entries<K: Clone>(entries: &Entries<K>, dark: &Dark) -> Entries<K> {
+    entries.iter().map(|(key, source, config)| {
+        let mut config = config.clone();
+        let source = match dark {
+            Dark::Path(path) => Source::Path(path.clone()),
+            Dark::Invert => { config.invert = true; source.clone() }
+        };
+
+        (key.clone(), source, config)
+    }).collect()
+}
+
+/// Inverts the luminance of `image`, leaving its chroma and transparency untouched,
+/// so that dark artwork becomes light and vice versa.
+pub fn invert(image: &mut RgbaImage) {
+    for px in image.pixels_mut() {
+        let luma = 0.299 * f32::from(px.0[0]) + 0.587 * f32::from(px.0[1]) + 0.114 * f32::from(px.0[2]);
+        let shift = 255.0 - 2.0 * luma;
+
+        for ch in 0..3 {
+            px.0[ch] = (f32::from(px.0[ch]) + shift).clamp(0.0, 255.0).round() as u8;
+        }
+    }
+}
+
+/// Links the dark variant saved in the `dark` folder of `path` from the HTML helper of
+/// the light variant, qualified with a `prefers-color-scheme` media query, and from its
+/// web app manifest, if any. The helpers of the dark variant itself are removed.
+pub fn link(path: &Path) -> io::Result<()> {
+    let dark_helper = path.join(FOLDER).join(helper::NAME);
+    let helper = fs::read_to_string(&dark_helper)?;
+    fs::remove_file(&dark_helper)?;
+
+    if path.join(FOLDER).join(helper::MANIFEST).is_file() {
+        link_manifest(path)?;
+    }
+
+    // Apple touch icons don't support media queries, so only icons are linked
+    let links: String = helper.lines()
+        .filter(|line| line.contains("rel=\"icon\""))
+        .map(|line| {
+            let href = format!("media=\"{}\" href=\"{}/", MEDIA, FOLDER);
+            format!("{}\n", line.replacen("href=\"", &href, 1))
+        })
+        .collect();
+
+    helper::append(path, &links)
+}
+
+/// Lists the icons of the web app manifest of the dark variant under the
+/// `color_scheme_dark` user preference of the manifest of the light variant.
+fn link_manifest(path: &Path) -> io::Result<()> {
+    let dark_manifest = path.join(FOLDER).join(helper::MANIFEST);
+    let icons = fs::read_to_string(&dark_manifest)?;
+    fs::remove_file(&dark_manifest)?;
+
+    // Both manifests are written by `icon_baker` and only hold an `icons` array
+    let icons = match (icons.find('['), icons.rfind(']')) {
+        (Some(start), Some(end)) if start < end => &icons[start + 1..end],
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed web app manifest"))
+    };
+
+    let icons: String = icons.trim_start_matches(&['\n', '\r'][..]).trim_end()
+        .lines()
+        .map(|line| format!("        {}\n", line.replacen("\"src\": \"", &format!("\"src\": \"{}/", FOLDER), 1)))
+        .collect();
+
+    let manifest = fs::read_to_string(path.join(helper::MANIFEST))?;
+    let manifest = manifest.trim_end().trim_end_matches('}').trim_end();
+
+    fs::write(path.join(helper::MANIFEST), format!(
+        "{},\n    \"user_preferences\": {{\n        \"color_scheme_dark\": {{\n            \"icons\": [\n{}            ]\n        }}\n    }}\n}}",
+        manifest,
+        icons
+    ))
+}
\ No newline at end of file
@@ -0,0 +1,357 @@
+use crate::{error, color::{self, ColorSpace}};
+use super::{Encode, png, optimized, palette::{Palette, BitDepth, ALPHA_THRESHOLD}};
+use std::{io, convert::TryFrom};
+use icon_baker::{
+    Icon, AsSize, SourceImage, Error,
+    image::{DynamicImage, RgbaImage, Rgb}
+};
+
+/// The sizes supported by the classic Mac OS icon types.
+const LEGACY_SIZES: [u32;3] = [16, 32, 48];
+
+/// The sizes supported by the retina icon types, in points.
+const RETINA_SIZES: [u32;5] = [16, 32, 128, 256, 512];
+
+/// The sizes `icon_baker` encodes as run-length encoded 24-bit entries and 8-bit masks,
+/// rather than as `png`.
+const RLE_SIZES: [u32;4] = [16, 32, 48, 128];
+
+/// An `icns` file. Unscaled 32-bit entries are encoded by `icon_baker`, while retina
+/// entries and entries of the classic Mac OS icon types are encoded by IconPie itself.
+pub struct Icns {
+    icon: icon_baker::icns::Icns,
+    retina: Vec<(u32, RgbaImage)>,
+    legacy: Vec<(u32, BitDepth, RgbaImage)>,
+    dither: bool,
+    color_space: ColorSpace,
+    optimize: Option<u8>,
+    saved: usize
+}
+
+/// Identifies an entry of an `icns` file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    TrueColor(<icon_baker::icns::Icns as Icon>::Key),
+    /// A _16x16@2x_, _32x32@2x_, _128x128@2x_, _256x256@2x_ or _512x512@2x_ 32-bit entry.
+    Retina(u32),
+    /// A _16x16_, _32x32_ or _48x48_ entry of 1, 4 or 8 bits, indexing the
+    /// classic Mac OS system palettes.
+    Legacy(u32, BitDepth)
+}
+
+impl Key {
+    pub fn new(size: u32, scale: u32, depth: u32) -> Result<Self, error::Error> {
+        match (BitDepth::from_bits(depth), scale) {
+            (None, _) => Err(error::Error::InvalidDepth(depth)),
+            // `ic10` is the only type of 1024x1024 pixels, so `1024` is treated as `512@2x`
+            (Some(BitDepth::ThirtyTwo), 1) if size == 1024 => Ok(Key::Retina(512)),
+            (Some(BitDepth::ThirtyTwo), 1) => <icon_baker::icns::Icns as Icon>::Key::try_from(size)
+                .map(Key::TrueColor)
+                .map_err(|_| error::Error::InvalidDimensions(size)),
+            (Some(BitDepth::ThirtyTwo), 2) if RETINA_SIZES.contains(&size) => Ok(Key::Retina(size)),
+            (Some(depth), 1) if LEGACY_SIZES.contains(&size) => Ok(Key::Legacy(size, depth)),
+            (Some(_), 1) => Err(error::Error::InvalidDimensions(size)),
+            (Some(_), _) => Err(error::Error::InvalidScale(size, scale))
+        }
+    }
+}
+
+impl AsSize for Key {
+    fn as_size(&self) -> u32 {
+        match self {
+            Key::TrueColor(key) => key.as_size(),
+            Key::Retina(size) => 2 * size,
+            Key::Legacy(size, _) => *size
+        }
+    }
+}
+
+impl Icns {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Icns {
+            icon: icon_baker::icns::Icns::with_capacity(capacity),
+            retina: Vec::new(),
+            legacy: Vec::new(),
+            dither: false,
+            color_space: ColorSpace::Srgb,
+            optimize: None,
+            saved: 0
+        }
+    }
+
+    pub fn add_entry<F: FnMut(&SourceImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        mut filter: F,
+        source: &SourceImage,
+        key: Key
+    ) -> Result<(), Error<Key>> {
+        // Entries that aren't encoded as `png` can't be tagged with an ICC profile, so
+        // they are converted back to sRGB
+        let color_space = self.color_space;
+        let srgb = |image: DynamicImage| match color_space {
+            ColorSpace::Srgb => image,
+            _ => DynamicImage::ImageRgba8(color::to_srgb(&image.to_rgba(), color_space))
+        };
+
+        match key {
+            Key::TrueColor(k) => self.icon.add_entry(|src, size| {
+                let image = filter(src, size)?;
+                Ok(if RLE_SIZES.contains(&size) { srgb(image) } else { image })
+            }, source, k).map_err(|err| match err {
+                Error::AlreadyIncluded(k) => Error::AlreadyIncluded(Key::TrueColor(k)),
+                Error::MismatchedDimensions(s, dim) => Error::MismatchedDimensions(s, dim),
+                Error::Io(err) => Error::Io(err)
+            }),
+            Key::Retina(size) => {
+                if self.retina.iter().any(|&(s, _)| s == size) {
+                    return Err(Error::AlreadyIncluded(key));
+                }
+
+                let image = filter(source, 2 * size).map_err(Error::Io)?;
+                self.retina.push((size, image.to_rgba()));
+
+                Ok(())
+            },
+            Key::Legacy(size, depth) => {
+                if self.legacy.iter().any(|&(s, d, _)| (s, d) == (size, depth)) {
+                    return Err(Error::AlreadyIncluded(key));
+                }
+
+                let image = filter(source, size).map_err(Error::Io)?;
+                self.legacy.push((size, depth, srgb(image).to_rgba()));
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether to apply dithering when reducing entries to the system palettes.
+    pub fn dither(&mut self, dither: bool) -> &mut Self {
+        self.dither = dither;
+        self
+    }
+
+    /// The color space of the entries. Unless it's sRGB, `png` encoded entries are
+    /// tagged with its ICC profile, while other entries are converted back to sRGB.
+    /// Must be set before entries are added.
+    pub fn color_space(&mut self, color_space: ColorSpace) -> &mut Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// The level `png` encoded entries are optimized at, if any. See `optimize::encode`.
+    pub fn optimize(&mut self, level: Option<u8>) -> &mut Self {
+        self.optimize = level;
+        self
+    }
+
+    /// The number of bytes the optimization of `png` encoded entries saved when the
+    /// icon was last written.
+    pub fn saved(&self) -> usize {
+        self.saved
+    }
+
+    /// Encodes the entries of the classic Mac OS icon types as `(OSType, data)` pairs.
+    fn legacy_chunks(&self) -> Vec<([u8;4], Vec<u8>)> {
+        let mut chunks = Vec::with_capacity(self.legacy.len());
+
+        for &size in LEGACY_SIZES.iter() {
+            let entries: Vec<_> = self.legacy.iter().filter(|(s, _, _)| *s == size).collect();
+
+            // 4-bit and 8-bit entries rely on the mask of the 1-bit entry of the same size,
+            // so it has to be included even if it wasn't explicitly requested
+            let mono = entries.iter()
+                .find(|(_, depth, _)| *depth == BitDepth::One)
+                .or_else(|| entries.first());
+
+            if let Some((_, _, image)) = mono {
+                chunks.push((ostype(size, BitDepth::One), mono_with_mask(image, self.dither)));
+            }
+
+            for (_, depth, image) in entries.iter().filter(|(_, d, _)| *d != BitDepth::One) {
+                chunks.push((ostype(size, *depth), indexed(image, *depth, self.dither)));
+            }
+        }
+
+        chunks
+    }
+}
+
+impl Encode for Icns {
+    fn write<W: io::Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let mut data = Vec::new();
+        self.icon.write(&mut data)?;
+
+        // Entries encoded by `icon_baker` are split up so that they can be optimized and
+        // tagged too. All entries are sorted by type, so that the output doesn't depend
+        // on the order they were added in
+        let mut chunks = split(&data);
+        for (size, image) in &self.retina {
+            chunks.push((retina_ostype(*size), png(image)?));
+        }
+
+        chunks.extend(self.legacy_chunks());
+        chunks.sort_by_key(|&(ostype, _)| ostype);
+
+        let icc = self.color_space.icc();
+        self.saved = 0;
+        data.truncate(8);
+
+        for (ostype, mut chunk) in chunks {
+            if chunk.starts_with(&color::PNG_SIGNATURE) {
+                chunk = optimized(chunk, self.optimize, true, &mut self.saved)?;
+
+                if let Some(icc) = &icc {
+                    chunk = color::tag_png(&chunk, icc, "Display P3");
+                }
+            }
+
+            append(&mut data, ostype, &chunk);
+        }
+
+        // Update the length of the file in the header
+        let len = (data.len() as u32).to_be_bytes();
+        data[4..8].copy_from_slice(&len);
+
+        w.write_all(&data)
+    }
+}
+
+/// Appends an `(OSType, data)` pair to an `icns` file.
+fn append(data: &mut Vec<u8>, ostype: [u8;4], chunk: &[u8]) {
+    data.extend_from_slice(&ostype);
+    data.extend_from_slice(&(8 + chunk.len() as u32).to_be_bytes());
+    data.extend_from_slice(chunk);
+}
+
+/// Splits an `icns` file into `(OSType, data)` pairs.
+fn split(data: &[u8]) -> Vec<([u8;4], Vec<u8>)> {
+    let mut chunks = Vec::new();
+    let mut i = 8;
+
+    while i + 8 <= data.len() {
+        let mut ostype = [0u8;4];
+        ostype.copy_from_slice(&data[i..i + 4]);
+
+        let len = u32::from_be_bytes([data[i + 4], data[i + 5], data[i + 6], data[i + 7]]) as usize;
+        if len < 8 || i + len > data.len() { break; }
+
+        chunks.push((ostype, Vec::from(&data[i + 8..i + len])));
+        i += len;
+    }
+
+    chunks
+}
+
+fn retina_ostype(size: u32) -> [u8;4] {
+    match size {
+        16  => *b"ic11",
+        32  => *b"ic12",
+        128 => *b"ic13",
+        256 => *b"ic14",
+        512 => *b"ic10",
+        _ => unreachable!("{0}x{0}@2x entries are not retina entries", size)
+    }
+}
+
+fn ostype(size: u32, depth: BitDepth) -> [u8;4] {
+    match (size, depth) {
+        (16, BitDepth::One)   => *b"ics#",
+        (32, BitDepth::One)   => *b"ICN#",
+        (48, BitDepth::One)   => *b"ich#",
+        (16, BitDepth::Four)  => *b"ics4",
+        (32, BitDepth::Four)  => *b"icl4",
+        (48, BitDepth::Four)  => *b"ich4",
+        (16, BitDepth::Eight) => *b"ics8",
+        (32, BitDepth::Eight) => *b"icl8",
+        (48, BitDepth::Eight) => *b"ich8",
+        _ => unreachable!("{}x{} {}-bit entries are not legacy entries", size, size, depth.bits())
+    }
+}
+
+/// Encodes `image` as a 1-bit entry followed by its 1-bit mask.
+fn mono_with_mask(image: &RgbaImage, dither: bool) -> Vec<u8> {
+    let mut data = packed(&palette::mono().indices(image, dither), 1);
+    let mask: Vec<Option<u8>> = image.pixels()
+        .map(|px| Some((px.0[3] >= ALPHA_THRESHOLD) as u8))
+        .collect();
+
+    data.extend(packed(&mask, 1));
+    data
+}
+
+/// Encodes `image` as a 4-bit or 8-bit entry indexing the matching system palette.
+fn indexed(image: &RgbaImage, depth: BitDepth, dither: bool) -> Vec<u8> {
+    let palette = match depth {
+        BitDepth::Four  => palette::four_bit(),
+        BitDepth::Eight => palette::eight_bit(),
+        _ => unreachable!("{}-bit entries don't index a system palette", depth.bits())
+    };
+
+    packed(&palette.indices(image, dither), depth.bits() as u8)
+}
+
+/// Packs `indices` into a big-endian bit stream with `bits` bits per pixel.
+/// Masked out pixels are set to `0`.
+#[allow(clippy::manual_div_ceil)]
+fn packed(indices: &[Option<u8>], bits: u8) -> Vec<u8> {
+    let per_byte = 8 / bits as usize;
+    let mut data = vec![0u8; (indices.len() + per_byte - 1) / per_byte];
+
+    for (i, index) in indices.iter().enumerate() {
+        let shift = 8 - bits - (i % per_byte) as u8 * bits;
+        data[i / per_byte] |= index.unwrap_or(0) << shift;
+    }
+
+    data
+}
+
+/// The classic Mac OS system palettes.
+mod palette {
+    use super::{Palette, Rgb};
+
+    /// The palette used by 1-bit entries: `0` is white and `1` is black.
+    pub fn mono() -> Palette {
+        Palette::new(vec![Rgb([0xFF, 0xFF, 0xFF]), Rgb([0x00, 0x00, 0x00])])
+    }
+
+    /// The 16 color system palette.
+    pub fn four_bit() -> Palette {
+        const COLORS: [[u8;3];16] = [
+            [0xFF, 0xFF, 0xFF], [0xFC, 0xF3, 0x05], [0xFF, 0x64, 0x02], [0xDD, 0x08, 0x06],
+            [0xF2, 0x08, 0x84], [0x46, 0x00, 0xA5], [0x00, 0x00, 0xD4], [0x02, 0xAB, 0xEA],
+            [0x1F, 0xB7, 0x14], [0x00, 0x64, 0x11], [0x56, 0x2C, 0x05], [0x90, 0x71, 0x3A],
+            [0xC0, 0xC0, 0xC0], [0x80, 0x80, 0x80], [0x40, 0x40, 0x40], [0x00, 0x00, 0x00]
+        ];
+
+        Palette::new(COLORS.iter().map(|&c| Rgb(c)).collect())
+    }
+
+    /// The 256 color system palette: a 6x6x6 color cube followed by ramps of
+    /// red, green, blue and gray, ending in black.
+    pub fn eight_bit() -> Palette {
+        const CUBE: [u8;6] = [0xFF, 0xCC, 0x99, 0x66, 0x33, 0x00];
+        const RAMP: [u8;10] = [0xEE, 0xDD, 0xBB, 0xAA, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+        let mut colors = Vec::with_capacity(256);
+
+        for &r in CUBE.iter() {
+            for &g in CUBE.iter() {
+                for &b in CUBE.iter() {
+                    colors.push(Rgb([r, g, b]));
+                }
+            }
+        }
+
+        // Black is moved to the end of the palette
+        colors.pop();
+
+        colors.extend(RAMP.iter().map(|&v| Rgb([v, 0, 0])));
+        colors.extend(RAMP.iter().map(|&v| Rgb([0, v, 0])));
+        colors.extend(RAMP.iter().map(|&v| Rgb([0, 0, v])));
+        colors.extend(RAMP.iter().map(|&v| Rgb([v, v, v])));
+        colors.push(Rgb([0, 0, 0]));
+
+        Palette::new(colors)
+    }
+}
\ No newline at end of file
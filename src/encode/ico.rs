@@ -0,0 +1,262 @@
+use crate::error;
+use super::{Encode, png, optimized, palette::{Palette, BitDepth, ALPHA_THRESHOLD}};
+use std::io;
+use icon_baker::{
+    AsSize, SourceImage, Error,
+    image::{DynamicImage, RgbaImage, Rgb}
+};
+
+const ICONDIR_SIZE: u32 = 6;
+const ICONDIRENTRY_SIZE: u32 = 16;
+const BITMAPINFOHEADER_SIZE: u32 = 40;
+
+/// The default value for `Ico::bmp_below`: only _256x256_ entries are stored as `png`.
+pub const DEFAULT_BMP_BELOW: u32 = 256;
+
+/// An `ico` file whose entries are stored either as `bmp` images with an AND mask
+/// or as `png` images, depending on their sizes and color depths.
+pub struct Ico {
+    entries: Vec<(Key, RgbaImage)>,
+    bmp_below: u32,
+    smallest: bool,
+    dither: bool,
+    optimize: Option<u8>,
+    saved: usize
+}
+
+/// Identifies an entry of an `ico` file by its size and color depth.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    size: u32,
+    depth: BitDepth
+}
+
+impl Key {
+    pub fn new(size: u32, scale: u32, depth: u32) -> Result<Self, error::Error> {
+        if scale != 1 {
+            return Err(error::Error::InvalidScale(size, scale));
+        }
+
+        let depth = BitDepth::from_bits(depth)
+            .ok_or(error::Error::InvalidDepth(depth))?;
+
+        if size == 0 || size > 256 {
+            return Err(error::Error::InvalidDimensions(size));
+        }
+
+        Ok(Key { size, depth })
+    }
+}
+
+impl AsSize for Key {
+    fn as_size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl Ico {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Ico {
+            entries: Vec::with_capacity(capacity),
+            bmp_below: DEFAULT_BMP_BELOW,
+            smallest: false,
+            dither: false,
+            optimize: None,
+            saved: 0
+        }
+    }
+
+    pub fn add_entry<F: FnMut(&SourceImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        mut filter: F,
+        source: &SourceImage,
+        key: Key
+    ) -> Result<(), Error<Key>> {
+        if self.entries.iter().any(|(k, _)| *k == key) {
+            return Err(Error::AlreadyIncluded(key));
+        }
+
+        let image = filter(source, key.size).map_err(Error::Io)?;
+        self.entries.push((key, image.to_rgba()));
+
+        Ok(())
+    }
+
+    /// 32-bit entries smaller than `size` are stored as `bmp`. All other 32-bit entries
+    /// are stored as `png`. Entries with lower color depths are always stored as `bmp`.
+    pub fn bmp_below(&mut self, size: u32) -> &mut Self {
+        self.bmp_below = size;
+        self
+    }
+
+    /// Whether 32-bit entries are stored as whichever of `bmp` and `png` is smaller,
+    /// regardless of `bmp_below`.
+    pub fn smallest(&mut self, smallest: bool) -> &mut Self {
+        self.smallest = smallest;
+        self
+    }
+
+    /// Whether to apply dithering when reducing the color depth of entries.
+    pub fn dither(&mut self, dither: bool) -> &mut Self {
+        self.dither = dither;
+        self
+    }
+
+    /// The level `png` entries are optimized at, if any. See `optimize::encode`.
+    /// Entries are kept in 8-bit RGBA, as the Windows `ico` decoder requires.
+    pub fn optimize(&mut self, level: Option<u8>) -> &mut Self {
+        self.optimize = level;
+        self
+    }
+
+    /// The number of bytes the optimization of `png` entries saved when the icon was
+    /// last written.
+    pub fn saved(&self) -> usize {
+        self.saved
+    }
+}
+
+impl Encode for Ico {
+    fn write<W: io::Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let mut data = Vec::with_capacity(self.entries.len());
+        self.saved = 0;
+
+        for (key, image) in &self.entries {
+            if key.depth != BitDepth::ThirtyTwo {
+                data.push(indexed_bmp(image, key.depth, self.dither));
+            } else if self.smallest {
+                let bmp = bmp(image);
+                let mut saved = 0;
+                let png = optimized(png(image)?, self.optimize, false, &mut saved)?;
+
+                if png.len() < bmp.len() {
+                    self.saved += saved;
+                    data.push(png);
+                } else {
+                    data.push(bmp);
+                }
+            } else if key.size < self.bmp_below {
+                data.push(bmp(image));
+            } else {
+                data.push(optimized(png(image)?, self.optimize, false, &mut self.saved)?);
+            }
+        }
+
+        w.write_all(&0u16.to_le_bytes())?;
+        w.write_all(&1u16.to_le_bytes())?;
+        w.write_all(&(self.entries.len() as u16).to_le_bytes())?;
+
+        let mut offset = ICONDIR_SIZE + ICONDIRENTRY_SIZE * self.entries.len() as u32;
+        for ((key, image), data) in self.entries.iter().zip(data.iter()) {
+            let (width, height) = image.dimensions();
+            // Palettes of 256 colors are represented as 0, just like dimensions of 256
+            let colors = key.depth.colors().unwrap_or(0) as u8;
+
+            w.write_all(&[width as u8, height as u8, colors, 0])?;
+            w.write_all(&1u16.to_le_bytes())?;
+            w.write_all(&key.depth.bits().to_le_bytes())?;
+            w.write_all(&(data.len() as u32).to_le_bytes())?;
+            w.write_all(&offset.to_le_bytes())?;
+
+            offset += data.len() as u32;
+        }
+
+        for data in data {
+            w.write_all(&data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Encodes `image` as a 32-bit `bmp` with an AND mask, without the `BITMAPFILEHEADER`.
+fn bmp(image: &RgbaImage) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let mut data = bitmap_header(width, height, BitDepth::ThirtyTwo);
+
+    // Rows are stored bottom-up
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let [r, g, b, a] = image.get_pixel(x, y).0;
+            data.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+
+    and_mask(&mut data, image, 1);
+    data
+}
+
+/// Encodes `image` as a palette-based `bmp` with an AND mask, without the `BITMAPFILEHEADER`.
+// `div_ceil` would require Rust 1.73
+#[allow(clippy::manual_div_ceil)]
+fn indexed_bmp(image: &RgbaImage, depth: BitDepth, dither: bool) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let colors = depth.colors().unwrap_or(256);
+    let bits = u32::from(depth.bits());
+
+    // Transparent pixels are black in the XOR mask, so that the AND mask leaves the
+    // background unchanged. A slot is only reserved for black if there are any
+    let transparent = image.pixels().any(|px| px.0[3] < ALPHA_THRESHOLD);
+    let mut palette = Palette::median_cut(image, if transparent { colors - 1 } else { colors });
+    let black = if transparent { palette.insert(Rgb([0, 0, 0])) as u8 } else { 0 };
+    let indices = palette.indices(image, dither);
+
+    let mut data = bitmap_header(width, height, depth);
+
+    for i in 0..colors {
+        let [r, g, b] = palette.colors().get(i).map_or([0, 0, 0], |c| c.0);
+        data.extend_from_slice(&[b, g, r, 0]);
+    }
+
+    let stride = ((width * bits + 31) / 32 * 4) as usize;
+    for y in (0..height).rev() {
+        let mut row = vec![0u8; stride];
+
+        for x in 0..width {
+            let index = indices[(y * width + x) as usize].unwrap_or(black);
+            let offset = x * bits;
+            row[(offset / 8) as usize] |= index << (8 - bits - offset % 8);
+        }
+
+        data.extend_from_slice(&row);
+    }
+
+    and_mask(&mut data, image, ALPHA_THRESHOLD);
+    data
+}
+
+fn bitmap_header(width: u32, height: u32, depth: BitDepth) -> Vec<u8> {
+    let mut data = Vec::with_capacity(BITMAPINFOHEADER_SIZE as usize + (4 * width * height) as usize);
+
+    data.extend_from_slice(&BITMAPINFOHEADER_SIZE.to_le_bytes());
+    data.extend_from_slice(&(width as i32).to_le_bytes());
+    // The height accounts for both the XOR and the AND masks
+    data.extend_from_slice(&(2 * height as i32).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&depth.bits().to_le_bytes());
+    // Compression, image size, resolution and palette information are all left blank
+    data.extend_from_slice(&[0; 24]);
+
+    data
+}
+
+/// Appends the AND mask of `image` to `data`. Pixels whose alpha is below `threshold`
+/// are marked as transparent.
+#[allow(clippy::manual_div_ceil)]
+fn and_mask(data: &mut Vec<u8>, image: &RgbaImage, threshold: u8) {
+    let (width, height) = image.dimensions();
+    let stride = ((width + 31) / 32 * 4) as usize;
+
+    // Rows are stored bottom-up
+    for y in (0..height).rev() {
+        let mut row = vec![0u8; stride];
+
+        for x in 0..width {
+            if image.get_pixel(x, y).0[3] < threshold {
+                row[(x / 8) as usize] |= 0x80 >> (x % 8);
+            }
+        }
+
+        data.extend_from_slice(&row);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,55 @@
+use std::{io, fs::File, path::Path};
+use icon_baker::{Icon, favicon::Favicon, image::{RgbaImage, ColorType, png::PNGEncoder}};
+
+pub mod ico;
+pub mod icns;
+pub mod optimize;
+pub mod palette;
+pub mod quantize;
+
+pub use ico::Ico;
+pub use icns::Icns;
+
+/// An icon that can be written to a stream or saved to disk.
+pub trait Encode {
+    fn write<W: io::Write>(&mut self, w: &mut W) -> io::Result<()>;
+
+    fn save<P: AsRef<Path>>(&mut self, path: &P) -> io::Result<()> {
+        let mut file = File::create(path.as_ref())?;
+        self.write(&mut file)
+    }
+}
+
+impl Encode for Favicon {
+    #[inline]
+    fn write<W: io::Write>(&mut self, w: &mut W) -> io::Result<()> {
+        Icon::write(self, w)
+    }
+
+    #[inline]
+    fn save<P: AsRef<Path>>(&mut self, path: &P) -> io::Result<()> {
+        Icon::save(self, path)
+    }
+}
+
+/// Encodes `image` as a 32-bit `png`.
+fn png(image: &RgbaImage) -> io::Result<Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let mut data = Vec::with_capacity((4 * width * height) as usize);
+
+    PNGEncoder::new(&mut data).encode(image, width, height, ColorType::RGBA(8))?;
+    Ok(data)
+}
+
+/// Optimizes the `png` file `data` at `level`, if specified, and adds the number of
+/// bytes saved to `saved`. See `optimize::encode` for `reduce`.
+fn optimized(data: Vec<u8>, level: Option<u8>, reduce: bool, saved: &mut usize) -> io::Result<Vec<u8>> {
+    match level {
+        Some(level) => {
+            let optimized = optimize::png(&data, level, reduce)?;
+            *saved += data.len() - optimized.len();
+            Ok(optimized)
+        },
+        None => Ok(data)
+    }
+}
\ No newline at end of file
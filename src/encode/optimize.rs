@@ -0,0 +1,378 @@
+use crate::color::{self, PNG_SIGNATURE};
+use std::{io, fs, path::Path, collections::HashMap};
+use icon_baker::image::{self, RgbaImage, ImageFormat};
+use deflate::Compression;
+
+/// The highest optimization level.
+pub const MAX_LEVEL: u8 = 3;
+
+/// The `png` color types.
+const GRAYSCALE: u8 = 0;
+const RGB: u8 = 2;
+const INDEXED: u8 = 3;
+const GRAYSCALE_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+/// The `png` filter types.
+const FILTERS: [Filter;5] = [Filter::None, Filter::Sub, Filter::Up, Filter::Average, Filter::Paeth];
+
+/// How the filter type of each scanline is chosen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Strategy {
+    /// Every scanline uses the same filter type.
+    Fixed(Filter),
+    /// Each scanline uses the filter type minimizing the sum of the absolute values of
+    /// its filtered bytes.
+    MinSum
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Filter {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4
+}
+
+/// The pixels of an image in a specific `png` color type and bit depth.
+struct Raw {
+    width: u32,
+    height: u32,
+    color_type: u8,
+    bit_depth: u8,
+    /// Unfiltered scanlines, without their filter type bytes.
+    rows: Vec<Vec<u8>>,
+    palette: Vec<[u8;4]>
+}
+
+/// Encodes `image` as the smallest `png` found at `level`, without altering a single
+/// pixel:
+/// * `1` compresses the image at the highest setting, choosing the filter type of
+///   each scanline heuristically.
+/// * `2` also reduces the color type and bit depth of the image where lossless,
+///   converting it to a palette if it holds at most 256 colors.
+/// * `3` also tries every filter type on every reduction.
+///
+/// If `reduce` is unset the image is kept in 8-bit RGBA at every level, since some
+/// decoders, such as that of `ico` files on Windows, only accept that color type.
+pub fn encode(image: &RgbaImage, level: u8, reduce: bool) -> Vec<u8> {
+    let candidates = if reduce && level >= 2 { reductions(image) } else { vec![Raw::rgba(image)] };
+
+    let mut smallest: Option<Vec<u8>> = None;
+    for raw in &candidates {
+        for &strategy in &strategies(raw, level) {
+            let data = raw.encode(strategy);
+
+            let smaller = match &smallest {
+                Some(smallest) => data.len() < smallest.len(),
+                None => true
+            };
+
+            if smaller {
+                smallest = Some(data);
+            }
+        }
+    }
+
+    smallest.expect("at least one candidate is always encoded")
+}
+
+/// Optimizes the `png` file `data` at `level`, keeping its color space information.
+/// Returns `data` itself if it can't be made any smaller. Files of 16 bits per
+/// channel are left untouched, since they can't be reduced without loss. See
+/// `encode` for `reduce`.
+pub fn png(data: &[u8], level: u8, reduce: bool) -> io::Result<Vec<u8>> {
+    let mut chunks = color::png_chunks(data);
+
+    match chunks.next() {
+        Some((kind, ihdr)) if kind == b"IHDR" && ihdr.len() > 8 => if ihdr[8] == 16 {
+            return Ok(Vec::from(data));
+        },
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a png file"))
+    }
+
+    let image = image::load_from_memory_with_format(data, ImageFormat::PNG)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+        .to_rgba();
+
+    let optimized = encode(&image, level, reduce);
+    let mut output = Vec::with_capacity(optimized.len());
+    output.extend_from_slice(&PNG_SIGNATURE);
+
+    // Color space information has to stay in front of `PLTE` and `IDAT`, so it's
+    // placed right after `IHDR`
+    for (kind, chunk) in color::png_chunks(&optimized) {
+        color::write_chunk(&mut output, kind, chunk);
+
+        if kind == b"IHDR" {
+            let info = color::png_chunks(data)
+                .filter(|(kind, _)| [&b"iCCP"[..], b"sRGB", b"gAMA", b"cHRM"].contains(kind));
+
+            for (kind, chunk) in info {
+                color::write_chunk(&mut output, kind, chunk);
+            }
+        }
+    }
+
+    Ok(if output.len() < data.len() { output } else { Vec::from(data) })
+}
+
+/// Optimizes every `png` file in the folder at `path` at `level`, returning the
+/// number of bytes saved.
+pub fn folder(path: &Path, level: u8) -> io::Result<usize> {
+    let mut saved = 0;
+
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => (),
+            _ => continue
+        }
+
+        let data = fs::read(&path)?;
+        let optimized = png(&data, level, true)?;
+
+        if optimized.len() < data.len() {
+            fs::write(&path, &optimized)?;
+            saved += data.len() - optimized.len();
+        }
+    }
+
+    Ok(saved)
+}
+
+/// The lossless representations of `image` worth trying: the smallest true-color
+/// color type and bit depth holding it, and a palette if it holds at most 256 colors.
+fn reductions(image: &RgbaImage) -> Vec<Raw> {
+    let opaque = image.pixels().all(|px| px.0[3] == 0xFF);
+    let gray = image.pixels().all(|px| px.0[0] == px.0[1] && px.0[1] == px.0[2]);
+
+    let mut candidates = Vec::with_capacity(2);
+    candidates.push(match (gray, opaque) {
+        (true, true) => Raw::gray(image),
+        (true, false) => Raw::new(image, GRAYSCALE_ALPHA, |px| vec![px[0], px[3]]),
+        (false, true) => Raw::new(image, RGB, |px| vec![px[0], px[1], px[2]]),
+        (false, false) => Raw::rgba(image)
+    });
+
+    if let Some(raw) = Raw::indexed(image) {
+        candidates.push(raw);
+    }
+
+    candidates
+}
+
+/// The strategies worth trying on `raw` at `level`. Images of fewer than 8 bits per
+/// pixel rarely benefit from filtering, so they're left unfiltered below level `3`.
+fn strategies(raw: &Raw, level: u8) -> Vec<Strategy> {
+    if level >= 3 {
+        let mut strategies: Vec<Strategy> = FILTERS.iter().map(|&f| Strategy::Fixed(f)).collect();
+        strategies.push(Strategy::MinSum);
+        strategies
+    } else if raw.color_type == INDEXED || raw.bit_depth < 8 {
+        vec![Strategy::Fixed(Filter::None)]
+    } else {
+        vec![Strategy::MinSum]
+    }
+}
+
+impl Raw {
+    fn new<F: Fn(&[u8;4]) -> Vec<u8>>(image: &RgbaImage, color_type: u8, channels: F) -> Self {
+        let (width, height) = image.dimensions();
+        let rows = (0..height).map(|y| {
+            (0..width).flat_map(|x| channels(&image.get_pixel(x, y).0)).collect()
+        }).collect();
+
+        Raw { width, height, color_type, bit_depth: 8, rows, palette: Vec::new() }
+    }
+
+    fn rgba(image: &RgbaImage) -> Self {
+        Raw::new(image, RGBA, |px| px.to_vec())
+    }
+
+    /// An opaque grayscale image, of the lowest bit depth representing each of its
+    /// levels exactly.
+    fn gray(image: &RgbaImage) -> Self {
+        let bit_depth = [1, 2, 4].iter().cloned()
+            .find(|&depth| {
+                let step = 0xFF / ((1u16 << depth) - 1) as u8;
+                image.pixels().all(|px| px.0[0] % step == 0)
+            })
+            .unwrap_or(8);
+
+        let step = 0xFF / ((1u16 << bit_depth) - 1) as u8;
+        let (width, height) = image.dimensions();
+        let rows = (0..height).map(|y| {
+            let levels: Vec<u8> = (0..width).map(|x| image.get_pixel(x, y).0[0] / step).collect();
+            packed(&levels, bit_depth)
+        }).collect();
+
+        Raw { width, height, color_type: GRAYSCALE, bit_depth, rows, palette: Vec::new() }
+    }
+
+    /// A palette of the colors of `image`, if it holds at most 256 of them. Colors
+    /// that aren't opaque are moved to the front, so that the `tRNS` chunk can leave
+    /// out the opaque ones.
+    fn indexed(image: &RgbaImage) -> Option<Self> {
+        let mut palette = Vec::new();
+        let mut indices = HashMap::new();
+        for px in image.pixels() {
+            if indices.insert(px.0, 0u8).is_none() {
+                if palette.len() == 256 { return None; }
+                palette.push(px.0);
+            }
+        }
+
+        palette.sort_by_key(|color| color[3] == 0xFF);
+        for (i, color) in palette.iter().enumerate() {
+            indices.insert(*color, i as u8);
+        }
+
+        let bit_depth = match palette.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8
+        };
+
+        let (width, height) = image.dimensions();
+        let rows = (0..height).map(|y| {
+            let row: Vec<u8> = (0..width).map(|x| indices[&image.get_pixel(x, y).0]).collect();
+            packed(&row, bit_depth)
+        }).collect();
+
+        Some(Raw { width, height, color_type: INDEXED, bit_depth, rows, palette })
+    }
+
+    /// The number of bytes filters look back by: the size of a pixel, rounded up to
+    /// a whole byte.
+    #[allow(clippy::manual_div_ceil)]
+    fn filter_distance(&self) -> usize {
+        let channels = match self.color_type {
+            GRAYSCALE | INDEXED => 1,
+            GRAYSCALE_ALPHA => 2,
+            RGB => 3,
+            _ => 4
+        };
+
+        ((channels * self.bit_depth as usize + 7) / 8).max(1)
+    }
+
+    fn encode(&self, strategy: Strategy) -> Vec<u8> {
+        let distance = self.filter_distance();
+        let mut filtered = Vec::with_capacity(self.rows.iter().map(|row| row.len() + 1).sum());
+        let empty = vec![0u8; self.rows.first().map_or(0, Vec::len)];
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let prev = if i == 0 { &empty } else { &self.rows[i - 1] };
+
+            let (filter, line) = match strategy {
+                Strategy::Fixed(filter) => (filter, filter.apply(row, prev, distance)),
+                Strategy::MinSum => FILTERS.iter()
+                    .map(|&filter| (filter, filter.apply(row, prev, distance)))
+                    .min_by_key(|(_, line)| line.iter().map(|&b| u32::from((b as i8).wrapping_abs() as u8)).sum::<u32>())
+                    .expect("FILTERS isn't empty")
+            };
+
+            filtered.push(filter as u8);
+            filtered.extend(line);
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        ihdr.extend_from_slice(&[self.bit_depth, self.color_type, 0, 0, 0]);
+
+        let mut output = Vec::with_capacity(filtered.len() / 2);
+        output.extend_from_slice(&PNG_SIGNATURE);
+        color::write_chunk(&mut output, b"IHDR", &ihdr);
+
+        if self.color_type == INDEXED {
+            let plte: Vec<u8> = self.palette.iter().flat_map(|color| color[..3].to_vec()).collect();
+            color::write_chunk(&mut output, b"PLTE", &plte);
+
+            let trns: Vec<u8> = self.palette.iter()
+                .take_while(|color| color[3] != 0xFF)
+                .map(|color| color[3])
+                .collect();
+
+            if !trns.is_empty() {
+                color::write_chunk(&mut output, b"tRNS", &trns);
+            }
+        }
+
+        color::write_chunk(&mut output, b"IDAT", &deflate::deflate_bytes_zlib_conf(&filtered, Compression::Best));
+        color::write_chunk(&mut output, b"IEND", &[]);
+
+        output
+    }
+}
+
+impl Filter {
+    /// Filters a scanline, given the unfiltered scanline above it.
+    fn apply(self, row: &[u8], prev: &[u8], distance: usize) -> Vec<u8> {
+        (0..row.len()).map(|i| {
+            let a = if i >= distance { row[i - distance] } else { 0 };
+            let b = prev[i];
+            let c = if i >= distance { prev[i - distance] } else { 0 };
+
+            let predictor = match self {
+                Filter::None => 0,
+                Filter::Sub => a,
+                Filter::Up => b,
+                Filter::Average => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                Filter::Paeth => paeth(a, b, c)
+            };
+
+            row[i].wrapping_sub(predictor)
+        }).collect()
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = ((p - i16::from(a)).abs(), (p - i16::from(b)).abs(), (p - i16::from(c)).abs());
+
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+/// Packs `values` of `bits` bits each into bytes, most significant bits first.
+fn packed(values: &[u8], bits: u8) -> Vec<u8> {
+    if bits == 8 { return Vec::from(values); }
+
+    let per_byte = (8 / bits) as usize;
+    values.chunks(per_byte).map(|chunk| {
+        chunk.iter().enumerate().fold(0u8, |byte, (i, &value)| {
+            byte | value << (8 - bits as usize * (i + 1))
+        })
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icon_baker::image::Rgba;
+
+    #[test]
+    fn rgba_is_kept_unless_reduced() {
+        // An opaque grayscale image of two levels, reducible to 1-bit grayscale
+        let image = RgbaImage::from_fn(8, 8, |x, _| {
+            if x % 2 == 0 { Rgba([0, 0, 0, 0xFF]) } else { Rgba([0xFF;4]) }
+        });
+
+        for level in 1..=MAX_LEVEL {
+            let data = encode(&image, level, false);
+            let (_, ihdr) = color::png_chunks(&data).next().unwrap();
+            assert_eq!(&ihdr[8..10], &[8, RGBA]);
+
+            let decoded = image::load_from_memory_with_format(&data, ImageFormat::PNG).unwrap().to_rgba();
+            assert_eq!(decoded.into_raw(), image.clone().into_raw());
+        }
+
+        let data = encode(&image, 2, true);
+        let (_, ihdr) = color::png_chunks(&data).next().unwrap();
+        assert_eq!(&ihdr[8..10], &[1, GRAYSCALE]);
+    }
+}
\ No newline at end of file
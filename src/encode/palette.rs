@@ -0,0 +1,199 @@
+use icon_baker::image::{Rgb, RgbaImage};
+
+/// Pixels whose alpha falls below this threshold are treated as fully transparent
+/// when an image is reduced to a palette.
+pub const ALPHA_THRESHOLD: u8 = 128;
+
+/// The number of bits used to encode the color of each pixel of an entry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BitDepth {
+    One,
+    Four,
+    Eight,
+    ThirtyTwo
+}
+
+/// An ordered set of opaque colors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette(Vec<Rgb<u8>>);
+
+impl BitDepth {
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            1  => Some(BitDepth::One),
+            4  => Some(BitDepth::Four),
+            8  => Some(BitDepth::Eight),
+            32 => Some(BitDepth::ThirtyTwo),
+            _  => None
+        }
+    }
+
+    pub fn bits(self) -> u16 {
+        match self {
+            BitDepth::One       => 1,
+            BitDepth::Four      => 4,
+            BitDepth::Eight     => 8,
+            BitDepth::ThirtyTwo => 32
+        }
+    }
+
+    /// The number of colors of a palette of this depth. Returns `None` for
+    /// true-color depths.
+    pub fn colors(self) -> Option<usize> {
+        match self {
+            BitDepth::ThirtyTwo => None,
+            _ => Some(1 << self.bits())
+        }
+    }
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Rgb<u8>>) -> Self {
+        Palette(colors)
+    }
+
+    /// Builds a palette of at most `len` colors approximating the opaque pixels of
+    /// `image`, using the median cut algorithm.
+    pub fn median_cut(image: &RgbaImage, len: usize) -> Self {
+        let pixels: Vec<[u8;3]> = image.pixels()
+            .filter(|px| px.0[3] >= ALPHA_THRESHOLD)
+            .map(|px| [px.0[0], px.0[1], px.0[2]])
+            .collect();
+
+        if pixels.is_empty() || len == 0 {
+            return Palette(Vec::new());
+        }
+
+        let mut boxes = vec![pixels];
+
+        while boxes.len() < len {
+            // Split the box with the widest range of values along that channel
+            let widest = boxes.iter()
+                .enumerate()
+                .map(|(i, b)| { let (channel, range) = widest_channel(b); (i, channel, range) })
+                .max_by_key(|&(_, _, range)| range);
+
+            match widest {
+                Some((i, channel, range)) if range > 0 => {
+                    let mut lower = boxes.swap_remove(i);
+                    lower.sort_unstable_by_key(|px| px[channel]);
+
+                    let upper = lower.split_off(lower.len() / 2);
+                    boxes.push(lower);
+                    boxes.push(upper);
+                },
+                _ => break
+            }
+        }
+
+        Palette(boxes.iter().map(|b| average(b)).collect())
+    }
+
+    /// Adds `color` to the palette, unless it is already present. Returns the index of `color`.
+    pub fn insert(&mut self, color: Rgb<u8>) -> usize {
+        if let Some(i) = self.0.iter().position(|&c| c == color) {
+            i
+        } else {
+            self.0.push(color);
+            self.0.len() - 1
+        }
+    }
+
+    #[inline]
+    pub fn colors(&self) -> &[Rgb<u8>] {
+        &self.0
+    }
+
+    /// The index of the color of the palette closest to `color`.
+    pub fn index_of(&self, color: [i32;3]) -> usize {
+        let mut closest = (0, i32::MAX);
+
+        for (i, c) in self.0.iter().enumerate() {
+            let dist = (0..3)
+                .map(|ch| (i32::from(c.0[ch]) - color[ch]).pow(2))
+                .sum::<i32>();
+
+            if dist < closest.1 {
+                closest = (i, dist);
+            }
+        }
+
+        closest.0
+    }
+
+    /// Maps each pixel of `image` to the index of a color of the palette, in row-major
+    /// order. Transparent pixels are mapped to `None`.
+    ///
+    /// If `dither` is set the quantization error is diffused to neighbouring opaque
+    /// pixels, using Floyd-Steinberg dithering.
+    pub fn indices(&self, image: &RgbaImage, dither: bool) -> Vec<Option<u8>> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let mut output = Vec::with_capacity(width * height);
+        let mut error = vec![[0i32;3]; width * height];
+
+        for (i, px) in image.pixels().enumerate() {
+            if px.0[3] < ALPHA_THRESHOLD {
+                output.push(None);
+                continue;
+            }
+
+            let mut color = [0i32;3];
+            for ch in 0..3 {
+                color[ch] = (i32::from(px.0[ch]) + error[i][ch] / 16).clamp(0, 255);
+            }
+
+            let index = self.index_of(color);
+            output.push(Some(index as u8));
+
+            if !dither { continue; }
+
+            let (x, y) = (i % width, i / width);
+            let mapped = self.0[index].0;
+            let diff: Vec<i32> = (0..3).map(|ch| color[ch] - i32::from(mapped[ch])).collect();
+
+            let mut diffuse = |x: usize, y: usize, weight: i32| {
+                if x < width && y < height {
+                    for ch in 0..3 {
+                        error[y * width + x][ch] += diff[ch] * weight;
+                    }
+                }
+            };
+
+            diffuse(x + 1, y, 7);
+            if x > 0 { diffuse(x - 1, y + 1, 3); }
+            diffuse(x, y + 1, 5);
+            diffuse(x + 1, y + 1, 1);
+        }
+
+        output
+    }
+}
+
+/// The channel with the widest range of values in `pixels`, along with that range.
+fn widest_channel(pixels: &[[u8;3]]) -> (usize, u8) {
+    let mut widest = (0, 0);
+
+    for ch in 0..3 {
+        let min = pixels.iter().map(|px| px[ch]).min().unwrap_or(0);
+        let max = pixels.iter().map(|px| px[ch]).max().unwrap_or(0);
+
+        if max - min > widest.1 {
+            widest = (ch, max - min);
+        }
+    }
+
+    widest
+}
+
+fn average(pixels: &[[u8;3]]) -> Rgb<u8> {
+    let mut sum = [0u64;3];
+
+    for px in pixels {
+        for ch in 0..3 {
+            sum[ch] += u64::from(px[ch]);
+        }
+    }
+
+    let len = pixels.len().max(1) as u64;
+    Rgb([(sum[0] / len) as u8, (sum[1] / len) as u8, (sum[2] / len) as u8])
+}
\ No newline at end of file
@@ -0,0 +1,244 @@
+use super::optimize;
+use std::{io, fs, path::Path, collections::HashMap};
+use icon_baker::image::{self, RgbaImage, Rgba, ImageFormat};
+
+/// The number of colors of the palettes entries are reduced to.
+const PALETTE_LEN: usize = 256;
+
+/// The number of k-means passes refining the palette found by median cut.
+const REFINEMENT_PASSES: usize = 5;
+
+/// A color in premultiplied alpha, with channels ranging from `0` to `1`, so that
+/// differences in color count less the more transparent they are.
+type Color = [f32;4];
+
+/// A box of the median cut algorithm: a set of `(color, count)` pairs.
+type ColorBox = Vec<(Color, u32)>;
+
+/// Reduces `image` to a palette of at most 256 colors, in the manner of
+/// _libimagequant_: a median cut of its colors in premultiplied alpha, refined by
+/// k-means. Pixels are mapped to their nearest palette color without dithering,
+/// which keeps flat areas compressible. Returns `None` if the result falls short of
+/// `quality`, from `0` to `100`, on the scale of _pngquant_.
+pub fn quantize(image: &RgbaImage, quality: u8) -> Option<RgbaImage> {
+    let mut histogram = HashMap::new();
+    for px in image.pixels() {
+        *histogram.entry(normalized(*px)).or_insert(0u32) += 1;
+    }
+
+    // Pixels are sorted so that the palette doesn't depend on the order of `histogram`
+    let mut pixels: Vec<_> = histogram.into_iter().collect();
+    pixels.sort_by_key(|&(px, _)| px.0);
+
+    let colors: ColorBox = pixels.iter().map(|&(px, count)| (premultiplied(px), count)).collect();
+    let palette = if colors.len() <= PALETTE_LEN {
+        colors.iter().map(|&(color, _)| color).collect()
+    } else {
+        refined(median_cut(colors.clone()), &colors)
+    };
+
+    let mut error = 0.0;
+    let mut remapped = HashMap::with_capacity(pixels.len());
+    for &(px, count) in &pixels {
+        let color = premultiplied(px);
+        let nearest = palette[nearest(&palette, &color)];
+
+        error += difference(&color, &nearest) * count as f32;
+        remapped.insert(px, unpremultiplied(&nearest));
+    }
+
+    let len = (image.width() * image.height()).max(1);
+    if error / len as f32 > max_error(quality) {
+        return None;
+    }
+
+    Some(RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        remapped[&normalized(*image.get_pixel(x, y))]
+    }))
+}
+
+/// Quantizes every `png` file in the folder at `path` at `quality`. Files that fall
+/// short of `quality`, or that wouldn't get any smaller, are kept in true-color.
+/// Returns the number of files quantized, the number of files in total and the number
+/// of bytes saved.
+pub fn folder(path: &Path, quality: u8) -> io::Result<(usize, usize, usize)> {
+    let (mut quantized, mut total, mut saved) = (0, 0, 0);
+
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => total += 1,
+            _ => continue
+        }
+
+        let data = fs::read(&path)?;
+        let image = image::load_from_memory_with_format(&data, ImageFormat::PNG)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+            .to_rgba();
+
+        if let Some(image) = quantize(&image, quality) {
+            // Level `2` is the lowest level converting images to palettes
+            let output = optimize::encode(&image, 2, true);
+
+            if output.len() < data.len() {
+                fs::write(&path, &output)?;
+                quantized += 1;
+                saved += data.len() - output.len();
+            }
+        }
+    }
+
+    Ok((quantized, total, saved))
+}
+
+/// Splits the box of `colors` until there are `PALETTE_LEN` boxes, always splitting
+/// the box of the largest weighted variance at the weighted median of its widest
+/// channel. Returns the weighted mean of each box.
+fn median_cut(colors: ColorBox) -> Vec<Color> {
+    let mut boxes = vec![colors];
+
+    while boxes.len() < PALETTE_LEN {
+        let (i, channel) = match boxes.iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let variances = variances(b);
+                let channel = (0..4).fold(0, |max, ch| if variances[ch] > variances[max] { ch } else { max });
+                (i, channel, variances[channel] * weight(b) as f32)
+            })
+            .fold(None, |max: Option<(usize, usize, f32)>, candidate| match max {
+                Some(max) if max.2 >= candidate.2 => Some(max),
+                _ => Some(candidate)
+            }) {
+            Some((i, channel, _)) => (i, channel),
+            None => break
+        };
+
+        let mut b = boxes.swap_remove(i);
+        b.sort_by(|x, y| x.0[channel].partial_cmp(&y.0[channel]).unwrap_or(std::cmp::Ordering::Equal));
+
+        let half = weight(&b) / 2;
+        let mut acc = 0;
+        let split = b.iter()
+            .position(|&(_, count)| { acc += count; acc >= half })
+            .unwrap_or(0)
+            .min(b.len() - 2) + 1;
+
+        let rest = b.split_off(split);
+        boxes.push(b);
+        boxes.push(rest);
+    }
+
+    boxes.iter().map(|b| mean(b)).collect()
+}
+
+/// Refines `palette` with k-means passes over `colors`: every color is assigned to
+/// its nearest palette entry, which is then moved to the weighted mean of its colors.
+fn refined(mut palette: Vec<Color>, colors: &[(Color, u32)]) -> Vec<Color> {
+    for _ in 0..REFINEMENT_PASSES {
+        let mut sums = vec![([0.0f32;4], 0u32); palette.len()];
+
+        for (color, count) in colors {
+            let (sum, weight) = &mut sums[nearest(&palette, color)];
+            for ch in 0..4 {
+                sum[ch] += color[ch] * *count as f32;
+            }
+
+            *weight += count;
+        }
+
+        for (entry, (sum, weight)) in palette.iter_mut().zip(sums) {
+            if weight > 0 {
+                for ch in 0..4 {
+                    entry[ch] = sum[ch] / weight as f32;
+                }
+            }
+        }
+    }
+
+    palette
+}
+
+/// The index of the entry of `palette` closest to `color`.
+fn nearest(palette: &[Color], color: &Color) -> usize {
+    let mut nearest = (0, f32::INFINITY);
+    for (i, entry) in palette.iter().enumerate() {
+        let difference = difference(entry, color);
+        if difference < nearest.1 {
+            nearest = (i, difference);
+        }
+    }
+
+    nearest.0
+}
+
+/// The squared distance between two colors.
+fn difference(a: &Color, b: &Color) -> f32 {
+    (0..4).map(|ch| (a[ch] - b[ch]) * (a[ch] - b[ch])).sum()
+}
+
+/// The maximum mean error of a given quality, as in _libimagequant_.
+fn max_error(quality: u8) -> f32 {
+    match quality {
+        0 => f32::INFINITY,
+        100 => 0.0,
+        _ => {
+            let quality = f32::from(quality);
+            let low_quality_fudge = (0.016 / (0.001 + quality) - 0.001).max(0.0);
+            low_quality_fudge + 2.5 / (210.0 + quality).powf(1.2) * (100.1 - quality) / 100.0
+        }
+    }
+}
+
+fn variances(b: &[(Color, u32)]) -> [f32;4] {
+    let mean = mean(b);
+    let weight = weight(b) as f32;
+    let mut variances = [0.0;4];
+
+    for (color, count) in b {
+        for ch in 0..4 {
+            variances[ch] += (color[ch] - mean[ch]) * (color[ch] - mean[ch]) * *count as f32 / weight;
+        }
+    }
+
+    variances
+}
+
+fn mean(b: &[(Color, u32)]) -> Color {
+    let weight = weight(b) as f32;
+    let mut mean = [0.0;4];
+
+    for (color, count) in b {
+        for ch in 0..4 {
+            mean[ch] += color[ch] * *count as f32 / weight;
+        }
+    }
+
+    mean
+}
+
+fn weight(b: &[(Color, u32)]) -> u32 {
+    b.iter().map(|&(_, count)| count).sum()
+}
+
+/// Fully transparent pixels are all the same, regardless of their colors.
+fn normalized(px: Rgba<u8>) -> Rgba<u8> {
+    if px.0[3] == 0 { Rgba([0;4]) } else { px }
+}
+
+fn premultiplied(px: Rgba<u8>) -> Color {
+    let alpha = f32::from(px.0[3]) / 255.0;
+    [
+        f32::from(px.0[0]) / 255.0 * alpha,
+        f32::from(px.0[1]) / 255.0 * alpha,
+        f32::from(px.0[2]) / 255.0 * alpha,
+        alpha
+    ]
+}
+
+fn unpremultiplied(color: &Color) -> Rgba<u8> {
+    let alpha = color[3];
+    let channel = |c: f32| if alpha > 0.0 { (c / alpha * 255.0).clamp(0.0, 255.0).round() as u8 } else { 0 };
+
+    Rgba([channel(color[0]), channel(color[1]), channel(color[2]), (alpha * 255.0).round() as u8])
+}
\ No newline at end of file
@@ -1,5 +1,5 @@
 use crate::Output;
-use std::{io, path::PathBuf, fmt::{self, Formatter, Display}};
+use std::{io, error, path::PathBuf, fmt::{self, Formatter, Display}};
 use icon_baker::AsSize;
 use crossterm::{style, Color};
 
@@ -14,23 +14,33 @@ pub enum Error {
     Syntax(SyntaxError),
     AlreadyIncluded(u32),
     InvalidDimensions(u32),
+    InvalidScale(u32, u32),
+    InvalidDepth(u32),
     File(FileError),
-    Output(io::Error, Output)
+    /// A source that can't be rendered with the options of its entry, along with
+    /// the reason.
+    Render(String, PathBuf),
+    Output(io::Error, Output),
+    /// The budget of `--max-bytes`, along with the size reached by each attempt.
+    OverBudget(usize, Vec<(String, usize)>)
 }
 
 impl Error {
     pub fn from_baker<K: AsSize>(err: icon_baker::Error<K>, path: PathBuf) -> Self {
         match err {
             icon_baker::Error::AlreadyIncluded(key) => Error::AlreadyIncluded(key.as_size()),
-            icon_baker::Error::Io(err) => Error::File(FileError(err, path)),
+            icon_baker::Error::Io(err) => match err.get_ref().and_then(|err| err.downcast_ref()) {
+                Some(RenderError(reason)) => Error::Render(reason.clone(), path),
+                None => Error::File(FileError(err, path))
+            },
             icon_baker::Error::MismatchedDimensions(_, dim) => unreachable!("{:?}", dim)
         }
     }
 }
 
-impl Into<io::Error> for Error {
-    fn into(self) -> io::Error {
-        match self {
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
             Error::File(FileError(err, _)) | Error::Output(err, _) => err,
             _  => io::Error::from(io::ErrorKind::InvalidInput),
         }
@@ -52,13 +62,67 @@ impl Display for Error {
                 style("[Invalid Dimensions]").with(Color::Red),
                 s
             ),
+            Error::InvalidScale(s, scale) => write!(
+                f,
+                "{0} Icons of {1}x{1}@{2}x dimensions are not supported.",
+                style("[Invalid Dimensions]").with(Color::Red),
+                s,
+                scale
+            ),
+            Error::InvalidDepth(d) => write!(
+                f,
+                "{} Icons of {}-bit color depth are not supported.",
+                style("[Invalid Depth]").with(Color::Red),
+                d
+            ),
+            Error::OverBudget(budget, attempts) => {
+                write!(
+                    f,
+                    "{} The icon doesn't fit in {} bytes. Attempts:",
+                    style("[Over Budget]").with(Color::Red),
+                    budget
+                )?;
+
+                for (attempt, size) in attempts {
+                    write!(f, "\n    {}: {} bytes", attempt, size)?;
+                }
+
+                Ok(())
+            },
             Error::Output(_err, _output) => unimplemented!(),
+            Error::Render(reason, path) => write!(
+                f,
+                "{} {} can't be rendered: {}.",
+                style("[Render Error]").with(Color::Red),
+                style(path.display()).with(Color::Blue),
+                reason
+            ),
             Error::File(err) => err.fmt(f),
             Error::Syntax(err) => err.fmt(f),
         }
     }
 }
 
+/// Raised while rendering an entry, when its options can't be applied to its
+/// source. It travels through `icon_baker` as an `io::Error`, so that
+/// `Error::from_baker` can tell it apart from actual IO errors.
+#[derive(Debug)]
+pub struct RenderError(pub String);
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for RenderError {}
+
+impl From<RenderError> for io::Error {
+    fn from(err: RenderError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
 impl From<FileError> for Error {
     fn from(err: FileError) -> Self {
         Self::File(err)
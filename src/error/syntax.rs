@@ -4,7 +4,9 @@ use crossterm::{style, Color};
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum SyntaxError {
     UnexpectedToken(usize),
-    UnexpectedEnd
+    UnexpectedEnd,
+    /// A token that was recognized but holds an invalid value, along with the reason.
+    InvalidValue(usize, &'static str)
 }
 
 impl Display for SyntaxError {
@@ -21,6 +23,16 @@ impl Display for SyntaxError {
                 style(args[*err_c].clone()).with(Color::Red),
                 style(args[(*err_c + 1)..].join(" ")).with(Color::Blue)
             ),
+            SyntaxError::InvalidValue(err_c, reason) => write!(
+                f,
+                "{} {} {} {} {}\n{}",
+                style("[Invalid Value]").with(Color::Red),
+                style("$ icon-pie").with(Color::Blue),
+                style(args[..*err_c].join(" ")).with(Color::Blue),
+                style(args[*err_c].clone()).with(Color::Red),
+                style(args[(*err_c + 1)..].join(" ")).with(Color::Blue),
+                reason
+            ),
             SyntaxError::UnexpectedEnd => write!(
                 f,
                 "{} {} {} {}\nType {} for more details on IconBaker's usage.",
@@ -0,0 +1,132 @@
+use crate::{compose, font, error::RenderError};
+use std::{io, fs, path::PathBuf};
+use icon_baker::image::{RgbaImage, Rgba};
+use rusttype::{Font, Scale, point};
+
+/// The number of samples taken along each axis of a pixel when rasterizing the
+/// built-in font.
+const SUBSAMPLES: u32 = 4;
+
+/// The fraction of an entry covered by its text, along its widest dimension.
+const COVERAGE: f32 = 0.8;
+
+/// The scale at which text is measured, before being fit into an entry.
+const REFERENCE_SCALE: f32 = 100.0;
+
+pub const DEFAULT_COLOR: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+
+/// A short text or an emoji, rendered directly at each size of an entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glyph {
+    pub text: String,
+    /// Emoji can't be rendered with the built-in font, so they require `font`.
+    pub emoji: bool,
+    /// A TrueType or OpenType font. The built-in bitmap font is used if absent.
+    pub font: Option<PathBuf>,
+    pub color: Rgba<u8>
+}
+
+impl Glyph {
+    /// Parses sources such as `text:A` or `emoji:🚀`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (text, emoji) = if let Some(text) = s.strip_prefix("text:") {
+            (text, false)
+        } else if let Some(text) = s.strip_prefix("emoji:") {
+            (text, true)
+        } else {
+            return None;
+        };
+
+        Some(Glyph { text: String::from(text), emoji, font: None, color: DEFAULT_COLOR })
+    }
+
+    /// Renders the text, centered on a transparent _`size`x`size`_ canvas.
+    pub fn render(&self, size: u32) -> io::Result<RgbaImage> {
+        match &self.font {
+            Some(path) => self.render_font(&fs::read(path)?, size),
+            None if self.emoji => Err(RenderError(String::from("emoji sources require a font")).into()),
+            None => Ok(self.render_builtin(size))
+        }
+    }
+
+    fn render_font(&self, data: &[u8], size: u32) -> io::Result<RgbaImage> {
+        let font = Font::from_bytes(data)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        if self.text.chars().any(|c| font.glyph(c).id().0 == 0) {
+            return Err(RenderError(format!("the font has no glyph for \"{}\"", self.text)).into());
+        }
+
+        let (width, height) = match bounds(&font, &self.text, REFERENCE_SCALE) {
+            Some((min_x, min_y, max_x, max_y)) => (max_x - min_x, max_y - min_y),
+            None => return Ok(RgbaImage::new(size, size))
+        };
+
+        let scale = REFERENCE_SCALE * COVERAGE * size as f32 / width.max(height) as f32;
+        let (min_x, min_y, max_x, max_y) = bounds(&font, &self.text, scale)
+            .unwrap_or((0, 0, 0, 0));
+
+        // Offset the text so that its bounds are centered
+        let x = (size as i32 - (max_x - min_x)) / 2 - min_x;
+        let y = (size as i32 - (max_y - min_y)) / 2 - min_y;
+
+        let mut output = RgbaImage::new(size, size);
+        for glyph in font.layout(&self.text, Scale::uniform(scale), point(0.0, 0.0)) {
+            let bounds = match glyph.pixel_bounding_box() {
+                Some(bounds) => bounds,
+                None => continue
+            };
+
+            glyph.draw(|gx, gy, coverage| {
+                let px = bounds.min.x + gx as i32 + x;
+                let py = bounds.min.y + gy as i32 + y;
+
+                if px >= 0 && py >= 0 && (px as u32) < size && (py as u32) < size {
+                    compose::blend(output.get_pixel_mut(px as u32, py as u32), self.color, coverage);
+                }
+            });
+        }
+
+        Ok(output)
+    }
+
+    fn render_builtin(&self, size: u32) -> RgbaImage {
+        let text = font::glyphs(&self.text);
+        let (width, height) = (font::width(&text) as f32, font::GLYPH_HEIGHT as f32);
+        let scale = COVERAGE * size as f32 / width.max(height);
+        let (x, y) = ((size as f32 - width * scale) / 2.0, (size as f32 - height * scale) / 2.0);
+
+        let mut output = RgbaImage::new(size, size);
+        for (px, py, pixel) in output.enumerate_pixels_mut() {
+            let mut lit = 0;
+
+            for i in 0..SUBSAMPLES {
+                for j in 0..SUBSAMPLES {
+                    let sx = px as f32 + (i as f32 + 0.5) / SUBSAMPLES as f32;
+                    let sy = py as f32 + (j as f32 + 0.5) / SUBSAMPLES as f32;
+
+                    if font::lit(&text, (sx - x) / scale, (sy - y) / scale) { lit += 1; }
+                }
+            }
+
+            compose::blend(pixel, self.color, lit as f32 / (SUBSAMPLES * SUBSAMPLES) as f32);
+        }
+
+        output
+    }
+}
+
+/// The pixel bounds of `text` laid out at `scale`, as `(min_x, min_y, max_x, max_y)`.
+fn bounds(font: &Font, text: &str, scale: f32) -> Option<(i32, i32, i32, i32)> {
+    font.layout(text, Scale::uniform(scale), point(0.0, 0.0))
+        .filter_map(|glyph| glyph.pixel_bounding_box())
+        .fold(None, |bounds, b| match bounds {
+            Some((min_x, min_y, max_x, max_y)) => Some((
+                b.min.x.min(min_x),
+                b.min.y.min(min_y),
+                b.max.x.max(max_x),
+                b.max.y.max(max_y)
+            )),
+            None => Some((b.min.x, b.min.y, b.max.x, b.max.y))
+        })
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+use std::{io::{self, Write}, fs::OpenOptions, path::Path};
+
+/// The name of the HTML helper written along with favicons.
+pub const NAME: &str = "helper.html";
+
+/// The name of the web app manifest written along with favicons by `--web-app`.
+pub const MANIFEST: &str = "app.webmanifest";
+
+/// Appends `links` to the HTML helper of the favicon saved at `path`.
+pub fn append(path: &Path, links: &str) -> io::Result<()> {
+    OpenOptions::new()
+        .append(true)
+        .open(path.join(NAME))?
+        .write_all(links.as_bytes())
+}
\ No newline at end of file
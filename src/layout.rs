@@ -0,0 +1,227 @@
+use crate::sample;
+use std::io;
+use icon_baker::{
+    resample, SourceImage,
+    image::{DynamicImage, RgbaImage, imageops}
+};
+
+/// Where the content of an entry is placed within its bounds.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Layout {
+    /// Crop the source image to the bounds of the pixels whose alpha exceeds this
+    /// threshold, before fitting it.
+    pub trim: Option<u8>,
+    pub padding: Padding,
+    pub fit: Fit,
+    pub align: Align
+}
+
+/// The transparent margin left on each side of an entry.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Padding {
+    /// A percentage of the size of the entry.
+    Percent(f32),
+    Pixels(u32)
+}
+
+/// How the source image of an entry is fit into a square.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Fit {
+    /// Scale the image to fit inside the square, leaving transparent borders.
+    #[default]
+    Contain,
+    /// Scale the image to cover the square, cropping its edges.
+    Cover,
+    /// Stretch the image to the square, disregarding its aspect ratio.
+    Fill
+}
+
+/// Which edges of the square the source image of an entry is aligned to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight
+}
+
+impl Layout {
+    /// Lays out `source` on a transparent _`size`x`size`_ canvas, using `filter` to
+    /// resample it.
+    pub fn render<F: FnMut(&SourceImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        source: &SourceImage,
+        size: u32,
+        mut filter: F
+    ) -> io::Result<DynamicImage> {
+        if *self == Layout::default() {
+            return filter(source, size);
+        }
+
+        let inner = self.padding.inner(size);
+        let square = match source {
+            SourceImage::Raster(image) => match self.trim {
+                Some(threshold) => self.square(trim(image.to_rgba(), threshold)),
+                None => self.square(image.to_rgba())
+            },
+            SourceImage::Svg(_) => self.square(self.rasterize(source, inner)?)
+        };
+
+        let scaled = filter(&SourceImage::from(DynamicImage::ImageRgba8(square)), inner)?;
+        let mut output = RgbaImage::new(size, size);
+        imageops::overlay(&mut output, &scaled.to_rgba(), (size - inner) / 2, (size - inner) / 2);
+
+        Ok(DynamicImage::ImageRgba8(output))
+    }
+
+    /// Rasterizes the vector graphic `source`, and trims it if required, at the
+    /// resolution at which its square measures `inner` pixels.
+    fn rasterize(&self, source: &SourceImage, inner: u32) -> io::Result<RgbaImage> {
+        let (width, height) = source.dimensions();
+        let scale = f64::from(inner) / self.fit.side(width, height);
+        let image = rasterize(source, scale)?;
+
+        let threshold = match self.trim {
+            Some(threshold) => threshold,
+            None => return Ok(image)
+        };
+
+        // Trimmed images are rasterized again, so that they don't have to be upscaled
+        let trimmed = trim(image, threshold);
+        let (width, height) = trimmed.dimensions();
+        let rescale = f64::from(inner) / self.fit.side(f64::from(width), f64::from(height));
+
+        if rescale > 1.0 {
+            Ok(trim(rasterize(source, scale * rescale)?, threshold))
+        } else {
+            Ok(trimmed)
+        }
+    }
+
+    /// Fits `image` into a square, at its original resolution.
+    fn square(&self, mut image: RgbaImage) -> RgbaImage {
+        let (width, height) = image.dimensions();
+        let (x, y) = self.align.offsets();
+
+        match self.fit {
+            _ if width == height => image,
+            Fit::Contain => {
+                let side = width.max(height);
+                let mut output = RgbaImage::new(side, side);
+                imageops::overlay(
+                    &mut output,
+                    &image,
+                    ((side - width) as f32 * x) as u32,
+                    ((side - height) as f32 * y) as u32
+                );
+
+                output
+            },
+            Fit::Cover => {
+                let side = width.min(height);
+                let (x, y) = (((width - side) as f32 * x) as u32, ((height - side) as f32 * y) as u32);
+
+                imageops::crop(&mut image, x, y, side, side).to_image()
+            },
+            Fit::Fill => {
+                let side = width.max(height);
+                sample::resize(&image, side, side, &sample::CATMULL_ROM, false)
+            }
+        }
+    }
+}
+
+impl Fit {
+    /// The side of the square an image of _`width`x`height`_ is fit into.
+    fn side(self, width: f64, height: f64) -> f64 {
+        match self {
+            Fit::Contain | Fit::Fill => width.max(height),
+            Fit::Cover => width.min(height)
+        }
+    }
+}
+
+impl Padding {
+    /// The size of the content of an entry of _`size`x`size`_, once padded.
+    pub fn inner(self, size: u32) -> u32 {
+        let padding = match self {
+            Padding::Percent(percent) => (size as f32 * percent / 100.0).round() as u32,
+            Padding::Pixels(pixels) => pixels
+        };
+
+        size.saturating_sub(2 * padding).max(1)
+    }
+}
+
+impl Align {
+    /// The horizontal and vertical position of the content of an entry within its
+    /// free space, from `0.0` (top-left) to `1.0` (bottom-right).
+    fn offsets(self) -> (f32, f32) {
+        match self {
+            Align::Center      => (0.5, 0.5),
+            Align::Top         => (0.5, 0.0),
+            Align::Bottom      => (0.5, 1.0),
+            Align::Left        => (0.0, 0.5),
+            Align::Right       => (1.0, 0.5),
+            Align::TopLeft     => (0.0, 0.0),
+            Align::TopRight    => (1.0, 0.0),
+            Align::BottomLeft  => (0.0, 1.0),
+            Align::BottomRight => (1.0, 1.0)
+        }
+    }
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding::Pixels(0)
+    }
+}
+
+/// Rasterizes the vector graphic `source` at `scale` times its original dimensions.
+/// The transparent borders added by `icon_baker` are cropped out.
+fn rasterize(source: &SourceImage, scale: f64) -> io::Result<RgbaImage> {
+    let (width, height) = source.dimensions();
+    let side = ((width.max(height) * scale).round() as u32).max(1);
+
+    let (content_width, content_height) = (
+        ((width * scale).round() as u32).max(1).min(side),
+        ((height * scale).round() as u32).max(1).min(side)
+    );
+
+    let mut image = resample::linear(source, side)?.to_rgba();
+    Ok(imageops::crop(
+        &mut image,
+        (side - content_width) / 2,
+        (side - content_height) / 2,
+        content_width,
+        content_height
+    ).to_image())
+}
+
+/// Crops `image` to the bounds of the pixels whose alpha exceeds `threshold`.
+/// Images with no such pixels are left untouched.
+fn trim(mut image: RgbaImage, threshold: u8) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+
+    for (x, y, px) in image.enumerate_pixels() {
+        if px.0[3] > threshold {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
+        }
+    }
+
+    if left >= right || top >= bottom {
+        return image;
+    }
+
+    imageops::crop(&mut image, left, top, right - left, bottom - top).to_image()
+}
\ No newline at end of file
@@ -1,12 +1,30 @@
 extern crate icon_baker;
 extern crate crossterm;
+extern crate rusttype;
+extern crate inflate;
+extern crate deflate;
+extern crate crc32fast;
+extern crate gif;
 
 mod parse;
 mod error;
 mod command;
+mod encode;
+mod sample;
+mod layout;
+mod compose;
+mod badge;
+mod font;
+mod glyph;
+mod color;
+mod dark;
+mod animation;
+mod helper;
+mod monochrome;
+mod pixel;
 
 use std::{env, io, path::{PathBuf}};
-use icon_baker::{resample, image::DynamicImage, SourceImage};
+use icon_baker::{resample, image::{DynamicImage, RgbaImage, imageops}, SourceImage};
 
 #[derive(Clone, Debug)]
 pub enum Output {
@@ -18,10 +36,42 @@ pub enum Output {
 pub enum ResamplingFilter {
     Nearest,
     Linear,
-    Cubic
+    Cubic,
+    Lanczos2,
+    Lanczos3,
+    Mitchell,
+    CatmullRom
 }
 
-pub type Entries<K> = Vec<(K, PathBuf, ResamplingFilter)>;
+/// How the source image of an entry is rendered at each of its sizes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryConfig {
+    pub filter: ResamplingFilter,
+    /// Whether to blend pixels in linear light, rather than in sRGB space.
+    pub linear_light: bool,
+    /// Replaces `filter` with integer scaling, if present.
+    pub pixel_art: Option<pixel::PixelArt>,
+    pub sharpen: sample::Sharpen,
+    pub layout: layout::Layout,
+    pub layers: Vec<compose::Layer>,
+    pub monochrome: Option<monochrome::Monochrome>,
+    pub background: Option<compose::Background>,
+    pub shape: Option<compose::Shape>,
+    pub badge: Option<badge::Badge>,
+    /// The color space sources are converted to.
+    pub color_space: color::ColorSpace,
+    /// Whether to invert the luminance of the entry, for dark variants.
+    pub invert: bool
+}
+
+/// The source of an entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Path(PathBuf),
+    Glyph(glyph::Glyph)
+}
+
+pub type Entries<K> = Vec<(K, Source, EntryConfig)>;
 
 #[macro_export]
 macro_rules! syntax {
@@ -29,11 +79,112 @@ macro_rules! syntax {
 }
 
 impl ResamplingFilter {
-    pub fn call(&self, source: &SourceImage, size: u32) -> io::Result<DynamicImage> {
+    /// Resamples `source` to a _`size`x`size`_ image. Since `icon_baker`'s filters
+    /// don't blend pixels with premultiplied alpha, only `nearest` is delegated to
+    /// `icon_baker`: `linear` and `cubic` are replaced by their IconPie counterparts.
+    pub fn call(&self, source: &SourceImage, size: u32, linear_light: bool) -> io::Result<DynamicImage> {
+        match self {
+            ResamplingFilter::Nearest    => resample::nearest(source, size),
+            ResamplingFilter::Linear     => sample::resample(source, size, &sample::TRIANGLE, linear_light),
+            ResamplingFilter::Cubic      => sample::resample(source, size, &sample::CUBIC, linear_light),
+            ResamplingFilter::Lanczos2   => sample::resample(source, size, &sample::LANCZOS2, linear_light),
+            ResamplingFilter::Lanczos3   => sample::resample(source, size, &sample::LANCZOS3, linear_light),
+            ResamplingFilter::Mitchell   => sample::resample(source, size, &sample::MITCHELL, linear_light),
+            ResamplingFilter::CatmullRom => sample::resample(source, size, &sample::CATMULL_ROM, linear_light)
+        }
+    }
+}
+
+impl EntryConfig {
+    /// Resamples `source` to a _`size`x`size`_ image and post-processes it.
+    pub fn render(&self, source: &SourceImage, size: u32) -> io::Result<DynamicImage> {
+        let image = self.layout.render(source, size, |source, size| match &self.pixel_art {
+            Some(pixel_art) => pixel_art.resample(source, size),
+            None => self.filter.call(source, size, self.linear_light)
+        })?;
+
+        // Raster sources are converted when they're opened, but vector graphics can
+        // only be converted once rasterized
+        let image = match source {
+            SourceImage::Svg(_) if self.color_space != color::ColorSpace::Srgb => {
+                DynamicImage::ImageRgba8(color::from_srgb(&image.to_rgba(), self.color_space))
+            },
+            _ => image
+        };
+
+        self.post_process(image, size)
+    }
+
+    /// Renders `glyph` at _`size`x`size`_ and post-processes it. Glyphs are rendered
+    /// directly at each size, rather than resampled.
+    pub fn render_glyph(&self, glyph: &glyph::Glyph, size: u32) -> io::Result<DynamicImage> {
+        let inner = self.layout.padding.inner(size);
+        let mut image = RgbaImage::new(size, size);
+        imageops::overlay(&mut image, &glyph.render(inner)?, (size - inner) / 2, (size - inner) / 2);
+
+        self.post_process(DynamicImage::ImageRgba8(image), size)
+    }
+
+    /// Sharpens `image` and stacks the layers on top of it. The result is reduced to a
+    /// silhouette if necessary, after which the badge is overlaid on top of it, and it's
+    /// placed on top of the background and clipped to the shape. Dark variants are
+    /// inverted last, so that every part of the entry is.
+    fn post_process(&self, image: DynamicImage, size: u32) -> io::Result<DynamicImage> {
+        let image = match self.sharpen.params(size) {
+            Some((amount, radius)) => {
+                DynamicImage::ImageRgba8(sample::sharpen(&image.to_rgba(), amount, radius))
+            },
+            None => image
+        };
+
+        let image = if self.layers.is_empty() && self.monochrome.is_none() && self.badge.is_none() {
+            image
+        } else {
+            let mut image = image.to_rgba();
+            compose::stack(&mut image, &self.layers, self.linear_light, self.color_space)?;
+
+            if let Some(monochrome) = &self.monochrome {
+                monochrome.apply(&mut image);
+            }
+
+            if let Some(badge) = &self.badge {
+                badge.overlay(&mut image);
+            }
+
+            DynamicImage::ImageRgba8(image)
+        };
+
+        let image = if self.background.is_none() && self.shape.is_none() {
+            image
+        } else {
+            let background = match &self.background {
+                Some(background) => Some(background.render(size, self.color_space, |source, size| {
+                    self.filter.call(source, size, self.linear_light)
+                })?),
+                None => None
+            };
+
+            DynamicImage::ImageRgba8(compose::compose(&image.to_rgba(), background, self.shape))
+        };
+
+        if !self.invert {
+            return Ok(image);
+        }
+
+        let mut image = image.to_rgba();
+        dark::invert(&mut image);
+
+        Ok(DynamicImage::ImageRgba8(image))
+    }
+}
+
+impl Source {
+    /// A path identifying the source in error messages.
+    pub fn path(&self) -> PathBuf {
         match self {
-            ResamplingFilter::Nearest => resample::nearest(source, size),
-            ResamplingFilter::Linear  => resample::linear(source, size),
-            ResamplingFilter::Cubic   => resample::cubic(source, size)
+            Source::Path(path) => path.clone(),
+            Source::Glyph(glyph) if glyph.emoji => PathBuf::from(format!("emoji:{}", glyph.text)),
+            Source::Glyph(glyph) => PathBuf::from(format!("text:{}", glyph.text))
         }
     }
 }
@@ -0,0 +1,51 @@
+use icon_baker::image::{RgbaImage, Rgba};
+
+pub const DEFAULT_COLOR: Rgba<u8> = Rgba([0x00, 0x00, 0x00, 0xFF]);
+
+/// Reduces an entry to a silhouette of a single color, as required by symbolic and
+/// themed icons.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Monochrome {
+    pub mode: Mode,
+    pub color: Rgba<u8>
+}
+
+/// How the opacity of the silhouette is derived from the entry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// The alpha channel is preserved, along with anti-aliased edges.
+    Alpha,
+    /// Pixels whose alpha exceeds the threshold are opaque, while all others are
+    /// transparent.
+    Threshold(u8),
+    /// Dark pixels are opaque and light pixels are transparent, so that details
+    /// drawn in lighter colors are cut out of the silhouette.
+    Luminance
+}
+
+impl Monochrome {
+    /// Replaces the color of every pixel of `image` and adjusts its alpha according
+    /// to `mode`.
+    pub fn apply(&self, image: &mut RgbaImage) {
+        let opacity = f32::from(self.color.0[3]) / 255.0;
+
+        for px in image.pixels_mut() {
+            let alpha = match self.mode {
+                Mode::Alpha => f32::from(px.0[3]),
+                Mode::Threshold(threshold) if px.0[3] > threshold => 255.0,
+                Mode::Threshold(_) => 0.0,
+                Mode::Luminance => {
+                    let luma = 0.299 * f32::from(px.0[0]) + 0.587 * f32::from(px.0[1]) + 0.114 * f32::from(px.0[2]);
+                    f32::from(px.0[3]) * (1.0 - luma / 255.0)
+                }
+            };
+
+            *px = Rgba([
+                self.color.0[0],
+                self.color.0[1],
+                self.color.0[2],
+                (alpha * opacity).round() as u8
+            ]);
+        }
+    }
+}
\ No newline at end of file
@@ -1,70 +1,182 @@
-use crate::{command::Command, ResamplingFilter, Output, Entries, syntax, error::{Error, SyntaxError}};
+use crate::{
+    command::Command, ResamplingFilter, EntryConfig, Output, Entries, Source, syntax,
+    sample::Sharpen, layout::{Layout, Padding, Fit, Align}, compose::{Background, Shape, Layer},
+    badge::{self, Badge, Style}, glyph::Glyph, color::ColorSpace, dark::Dark,
+    monochrome::{self, Monochrome, Mode}, pixel::{PixelArt, Upscaler}, encode::optimize,
+    error::{Error, SyntaxError}
+};
 use super::{Token, TokenStream, Flag};
-use std::{io, path::PathBuf, iter::Iterator};
-use icon_baker::Icon;
+use std::{path::{Path, PathBuf}, iter::Iterator};
+use icon_baker::image::Rgba;
 
-pub fn entries<I: Icon,F: FnMut(u32) -> io::Result<<I as Icon>::Key>>(
+/// The color depth of entries for which no `--depth` is specified.
+const DEFAULT_DEPTH: u32 = 32;
+
+pub fn entries<K, F: FnMut(u32, u32, u32) -> Result<K, Error>>(
     mut converter: F,
     it: &mut TokenStream,
     n_entries: usize
-) -> Result<Entries<<I as Icon>::Key>, Error> {
-    let mut entries: Entries<<I as Icon>::Key> = Vec::with_capacity(n_entries);
+) -> Result<Entries<K>, Error> {
+    let mut entries: Entries<K> = Vec::with_capacity(n_entries);
     it.next();
 
     while let Some(&(_, Token::Flag(Flag::Entry))) = it.peek() {
-        entry::<I, _>(|size| converter(size), it, &mut entries)?;
+        entry(&mut converter, it, &mut entries)?;
     }
 
     Ok(entries)
 }
 
-pub fn entry<I: Icon, F: FnMut(u32) -> io::Result<<I as Icon>::Key>>(
+pub fn entry<K, F: FnMut(u32, u32, u32) -> Result<K, Error>>(
     adder: F,
     it: &mut TokenStream,
-    entries: &mut Entries<<I as Icon>::Key>
+    entries: &mut Entries<K>
 ) -> Result<(), Error> {
+    let mut paths: Vec<PathBuf> = Vec::with_capacity(1);
+    let mut first = 0;
+
     it.next();
+    while let Some(&(c, Token::Path(path))) = it.peek() {
+        // Glyphs are rendered at every size, so they can't be mixed with other sources
+        if !paths.is_empty() && (is_glyph(path) || is_glyph(&paths[0])) {
+            return syntax!(SyntaxError::UnexpectedToken(c));
+        }
+
+        if paths.is_empty() {
+            first = c;
+        }
+
+        paths.push(path.clone());
+        it.next();
+    }
+
     match it.peek() {
-        Some(&(_, Token::Path(path))) => keys::<I, _>(adder, it, entries, path),
+        _ if !paths.is_empty() => keys(adder, it, entries, &paths, first),
         Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c)),
         None => syntax!(SyntaxError::UnexpectedEnd)
     }
 }
 
-fn keys<I: Icon, F: FnMut(u32) -> io::Result<<I as Icon>::Key>>(
+fn keys<K, F: FnMut(u32, u32, u32) -> Result<K, Error>>(
     mut converter: F,
     it: &mut TokenStream,
-    entries: &mut Entries<<I as Icon>::Key>,
-    path: &PathBuf
+    entries: &mut Entries<K>,
+    paths: &[PathBuf],
+    first: usize
 ) -> Result<(), Error> {
     // TODO Preallocate this Vec
     let mut sizes = Vec::with_capacity(0);
 
-    it.next();
     match it.peek() {
-        Some(&(_, Token::Size(_))) => {
-            while let Some(&(_, Token::Size(size))) = it.peek() {
-                it.next();
-                sizes.push(*size);
+        Some(&(_, Token::Size(_))) | Some(&(_, Token::ScaledSize(_, _))) => loop {
+            match it.peek() {
+                Some(&(_, &Token::Size(size))) => sizes.push((size, 1)),
+                Some(&(_, &Token::ScaledSize(size, scale))) => sizes.push((size, scale)),
+                _ => break
             }
+
+            it.next();
         },
         Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
         None => return syntax!(SyntaxError::UnexpectedEnd)
     }
 
-    let filter = filter(it)?;
+    let mut glyph = paths[0].to_str().and_then(Glyph::parse);
+    let mut config = EntryConfig {
+        filter: ResamplingFilter::Nearest,
+        linear_light: false,
+        pixel_art: None,
+        sharpen: Sharpen::None,
+        layout: Layout::default(),
+        layers: Vec::new(),
+        monochrome: None,
+        background: None,
+        shape: None,
+        badge: None,
+        color_space: ColorSpace::Srgb,
+        invert: false
+    };
+    let mut depth = DEFAULT_DEPTH;
 
-    for size in sizes {
-        if let Ok(key) = converter(size) {
-            entries.push((key, path.clone(), filter));
-        } else {
-            return Err(Error::InvalidDimensions(size));
+    // The options of an entry may be listed in any order
+    while let Some(&(_, &Token::Flag(option))) = it.peek() {
+        match option {
+            Flag::Font | Flag::TextColor if glyph.is_some() => glyph_options(it, glyph.as_mut().unwrap())?,
+            Flag::Resample => config.filter = filter(it)?,
+            Flag::LinearLight => { it.next(); config.linear_light = true; },
+            Flag::PixelArt => config.pixel_art = pixel_art(it),
+            Flag::Sharpen => config.sharpen = sharpen(it)?,
+            Flag::Trim | Flag::Padding | Flag::Fit | Flag::Align => layout(it, &mut config.layout)?,
+            Flag::Layer => config.layers.extend(layers(it)?),
+            Flag::Monochrome => config.monochrome = monochrome(it)?,
+            Flag::Background => config.background = background(it)?,
+            Flag::Shape => config.shape = shape(it)?,
+            Flag::Depth => depth = self::depth(it)?,
+            _ => break
         }
     }
 
+    if let Some(Glyph { emoji: true, font: None, .. }) = glyph {
+        return syntax!(SyntaxError::InvalidValue(first, "Emoji sources require a `--font`."));
+    }
+
+    for (size, scale) in sizes {
+        let source = match &glyph {
+            Some(glyph) => Source::Glyph(glyph.clone()),
+            None => Source::Path(source(paths, size * scale).clone())
+        };
+
+        entries.push((converter(size, scale, depth)?, source, config.clone()));
+    }
+
     Ok(())
 }
 
+/// Whether `path` is a glyph source, such as `text:A`.
+fn is_glyph(path: &Path) -> bool {
+    path.to_str().and_then(Glyph::parse).is_some()
+}
+
+/// Parses `[--font FILE] [--text-color COLOR]`.
+fn glyph_options(it: &mut TokenStream, glyph: &mut Glyph) -> Result<(), Error> {
+    if flag(it, Flag::Font) {
+        match it.peek() {
+            Some(&(_, Token::Path(path))) => { it.next(); glyph.font = Some(path.clone()); },
+            Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            None => return syntax!(SyntaxError::UnexpectedEnd)
+        }
+    }
+
+    if flag(it, Flag::TextColor) {
+        glyph.color = color_arg(it)?;
+    }
+
+    Ok(())
+}
+
+/// Picks the source in `paths` designed for the size closest to `size`, favoring
+/// downscaling over upscaling. Sources tagged with a size, such as `logo-16.svg`,
+/// are designed for that size, while untagged sources are designed for any size
+/// larger than that of every tagged source.
+fn source(paths: &[PathBuf], size: u32) -> &PathBuf {
+    let tagged = |path: &&PathBuf| designed_size(path).unwrap_or(u32::MAX);
+
+    paths.iter()
+        .filter(|path| tagged(path) >= size)
+        .min_by_key(tagged)
+        .or_else(|| paths.iter().max_by_key(tagged))
+        .expect("entries have at least one source")
+}
+
+/// Parses the size tag at the end of the file stem of `path`, as in `logo-16.svg`
+/// or `logo_16.png`.
+fn designed_size(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_str()?;
+    let tag = stem.rsplit(&['-', '_'][..]).next()?;
+
+    if tag.len() < stem.len() { tag.parse().ok() } else { None }
+}
+
 fn filter(it: &mut TokenStream) -> Result<ResamplingFilter, Error> {
     if let Some((_, Token::Flag(Flag::Resample))) = it.peek() {
         it.next();
@@ -78,6 +190,452 @@ fn filter(it: &mut TokenStream) -> Result<ResamplingFilter, Error> {
     Ok(ResamplingFilter::Nearest)
 }
 
+/// Consumes `flag` if it's the next token. Returns whether it was present.
+fn flag(it: &mut TokenStream, flag: Flag) -> bool {
+    match it.peek() {
+        Some(&(_, &Token::Flag(f))) if f == flag => { it.next(); true },
+        _ => false
+    }
+}
+
+/// Parses `[--pixel-art [snap] [scale2x]]`.
+fn pixel_art(it: &mut TokenStream) -> Option<PixelArt> {
+    if !flag(it, Flag::PixelArt) {
+        return None;
+    }
+
+    let mut pixel_art = PixelArt { snap: false, upscaler: Upscaler::Nearest };
+
+    if let Some(&(_, Token::Path(path))) = it.peek() {
+        if path.to_str() == Some("snap") { it.next(); pixel_art.snap = true; }
+    }
+
+    if let Some(&(_, Token::Path(path))) = it.peek() {
+        if path.to_str() == Some("scale2x") { it.next(); pixel_art.upscaler = Upscaler::Scale2x; }
+    }
+
+    Some(pixel_art)
+}
+
+/// Parses `--sharpen [AMOUNT,RADIUS]`. Parameters are tokenized as paths, since they
+/// contain a comma.
+fn sharpen(it: &mut TokenStream) -> Result<Sharpen, Error> {
+    if !flag(it, Flag::Sharpen) {
+        return Ok(Sharpen::None);
+    }
+
+    let (c, (amount, radius)) = match it.peek() {
+        Some(&(c, Token::Path(path))) => match path.to_str().and_then(pair) {
+            Some(params) => (c, params),
+            None => return Ok(Sharpen::Auto)
+        },
+        _ => return Ok(Sharpen::Auto)
+    };
+
+    it.next();
+    if !amount.is_finite() || amount < 0.0 {
+        syntax!(SyntaxError::InvalidValue(c, "The amount of `--sharpen` can't be negative."))
+    } else if !radius.is_finite() || radius <= 0.0 {
+        syntax!(SyntaxError::InvalidValue(c, "The radius of `--sharpen` must be greater than 0."))
+    } else {
+        Ok(Sharpen::Manual { amount, radius })
+    }
+}
+
+/// Parses strings such as `0.5,1`.
+fn pair(s: &str) -> Option<(f32, f32)> {
+    let mut parts = s.splitn(2, ',');
+    let first = parts.next()?.parse::<f32>().ok()?;
+    let second = parts.next()?.parse::<f32>().ok()?;
+
+    Some((first, second))
+}
+
+/// Parses a number satisfying `valid`. Integers are tokenized as sizes, while other
+/// numbers are tokenized as paths.
+fn number<F: Fn(f32) -> bool>(it: &mut TokenStream, valid: F) -> Result<f32, Error> {
+    let (c, number) = match it.peek() {
+        Some(&(c, &Token::Size(n))) => (c, Some(n as f32)),
+        Some(&(c, Token::Path(path))) => (c, path.to_str().and_then(|s| s.parse::<f32>().ok())),
+        Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+        None => return syntax!(SyntaxError::UnexpectedEnd)
+    };
+
+    match number {
+        Some(number) if valid(number) => { it.next(); Ok(number) },
+        _ => syntax!(SyntaxError::UnexpectedToken(c))
+    }
+}
+
+/// Parses `[--trim [THRESHOLD]] [--padding (PERCENT% | PIXELS)] [--fit FIT] [--align ALIGN]`
+/// into `layout`.
+fn layout(it: &mut TokenStream, layout: &mut Layout) -> Result<(), Error> {
+    if flag(it, Flag::Trim) {
+        match it.peek() {
+            Some(&(_, &Token::Size(threshold))) if threshold < 255 => {
+                it.next();
+                layout.trim = Some(threshold as u8);
+            },
+            Some(&(c, &Token::Size(_))) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            _ => layout.trim = Some(0)
+        }
+    }
+
+    if flag(it, Flag::Padding) {
+        layout.padding = match it.peek() {
+            Some(&(_, &Token::Size(pixels))) => Padding::Pixels(pixels),
+            Some(&(c, Token::Path(path))) => match path.to_str().and_then(percent) {
+                Some(percent) => Padding::Percent(percent),
+                None => return syntax!(SyntaxError::UnexpectedToken(c))
+            },
+            Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            None => return syntax!(SyntaxError::UnexpectedEnd)
+        };
+
+        it.next();
+    }
+
+    if flag(it, Flag::Fit) {
+        layout.fit = match it.peek() {
+            Some(&(c, Token::Path(path))) => match path.to_str() {
+                Some("contain") => Fit::Contain,
+                Some("cover") => Fit::Cover,
+                Some("fill") => Fit::Fill,
+                _ => return syntax!(SyntaxError::UnexpectedToken(c))
+            },
+            Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            None => return syntax!(SyntaxError::UnexpectedEnd)
+        };
+
+        it.next();
+    }
+
+    if flag(it, Flag::Align) {
+        layout.align = match it.peek() {
+            Some(&(c, Token::Path(path))) => match path.to_str() {
+                Some("center") => Align::Center,
+                Some("top") => Align::Top,
+                Some("bottom") => Align::Bottom,
+                Some("left") => Align::Left,
+                Some("right") => Align::Right,
+                Some("top-left") => Align::TopLeft,
+                Some("top-right") => Align::TopRight,
+                Some("bottom-left") => Align::BottomLeft,
+                Some("bottom-right") => Align::BottomRight,
+                _ => return syntax!(SyntaxError::UnexpectedToken(c))
+            },
+            Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            None => return syntax!(SyntaxError::UnexpectedEnd)
+        };
+
+        it.next();
+    }
+
+    Ok(())
+}
+
+/// Parses strings such as `12.5%`.
+fn percent(s: &str) -> Option<f32> {
+    if !s.ends_with('%') { return None; }
+
+    match s[..s.len() - 1].parse::<f32>() {
+        Ok(percent) if (0.0..50.0).contains(&percent) => Some(percent),
+        _ => None
+    }
+}
+
+/// Parses `(--layer FILE [-r FILTER] [--offset X,Y] [--scale SCALE] [--opacity OPACITY])...`.
+fn layers(it: &mut TokenStream) -> Result<Vec<Layer>, Error> {
+    let mut layers = Vec::new();
+
+    while flag(it, Flag::Layer) {
+        let path = match it.peek() {
+            Some(&(_, Token::Path(path))) => { it.next(); path.clone() },
+            Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            None => return syntax!(SyntaxError::UnexpectedEnd)
+        };
+
+        let filter = filter(it)?;
+        let offset = if flag(it, Flag::Offset) {
+            match it.peek() {
+                Some(&(c, Token::Path(offset))) => match offset.to_str().and_then(pair) {
+                    Some(offset) => { it.next(); offset },
+                    None => return syntax!(SyntaxError::UnexpectedToken(c))
+                },
+                Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+                None => return syntax!(SyntaxError::UnexpectedEnd)
+            }
+        } else {
+            (0.0, 0.0)
+        };
+
+        let scale = if flag(it, Flag::Scale) { number(it, |scale| scale > 0.0)? } else { 1.0 };
+        let opacity = if flag(it, Flag::Opacity) {
+            number(it, |opacity| (0.0..=1.0).contains(&opacity))?
+        } else {
+            1.0
+        };
+
+        layers.push(Layer { path, filter, offset, scale, opacity });
+    }
+
+    Ok(layers)
+}
+
+/// Parses `[--monochrome [alpha | threshold:THRESHOLD | luminance] [COLOR]]`.
+fn monochrome(it: &mut TokenStream) -> Result<Option<Monochrome>, Error> {
+    if !flag(it, Flag::Monochrome) {
+        return Ok(None);
+    }
+
+    let mut monochrome = Monochrome { mode: Mode::Alpha, color: monochrome::DEFAULT_COLOR };
+
+    if let Some(&(c, Token::Path(path))) = it.peek() {
+        let mode = match path.to_str() {
+            Some("alpha") => Some(Mode::Alpha),
+            Some("luminance") => Some(Mode::Luminance),
+            Some(s) if s.starts_with("threshold:") => match s["threshold:".len()..].parse::<u8>() {
+                Ok(threshold) if threshold < 255 => Some(Mode::Threshold(threshold)),
+                _ => return syntax!(SyntaxError::UnexpectedToken(c))
+            },
+            _ => None
+        };
+
+        if let Some(mode) = mode {
+            it.next();
+            monochrome.mode = mode;
+        }
+    }
+
+    if let Some(&(_, Token::Path(path))) = it.peek() {
+        if let Some(color) = path.to_str().and_then(color) {
+            it.next();
+            monochrome.color = color;
+        }
+    }
+
+    Ok(Some(monochrome))
+}
+
+/// Parses `[--background (COLOR | COLOR,COLOR | PATH)]`.
+fn background(it: &mut TokenStream) -> Result<Option<Background>, Error> {
+    if !flag(it, Flag::Background) {
+        return Ok(None);
+    }
+
+    let background = match it.peek() {
+        Some(&(c, Token::Path(path))) => match path.to_str() {
+            Some(s) if s.starts_with('#') => {
+                let mut colors = s.splitn(2, ',');
+                match (colors.next().and_then(color), colors.next().map(color)) {
+                    (Some(color), None) => Background::Color(color),
+                    (Some(top), Some(Some(bottom))) => Background::Gradient(top, bottom),
+                    _ => return syntax!(SyntaxError::UnexpectedToken(c))
+                }
+            },
+            _ => Background::Image(path.clone())
+        },
+        Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+        None => return syntax!(SyntaxError::UnexpectedEnd)
+    };
+
+    it.next();
+    Ok(Some(background))
+}
+
+/// Parses `[--shape (rounded:RADIUS | continuous:RADIUS | circle | squircle)]`.
+fn shape(it: &mut TokenStream) -> Result<Option<Shape>, Error> {
+    if !flag(it, Flag::Shape) {
+        return Ok(None);
+    }
+
+    let shape = match it.peek() {
+        Some(&(c, Token::Path(path))) => match path.to_str() {
+            Some("circle") => Shape::Circle,
+            Some("squircle") => Shape::Squircle,
+            Some(s) if s.starts_with("rounded:") || s.starts_with("continuous:") => {
+                let mut parts = s.splitn(2, ':');
+                let kind = parts.next();
+
+                match parts.next().map(|r| r.trim_end_matches('%').parse::<f32>()) {
+                    Some(Ok(radius)) if (0.0..=50.0).contains(&radius) => match kind {
+                        Some("rounded") => Shape::Rounded(radius),
+                        _ => Shape::Continuous(radius)
+                    },
+                    _ => return syntax!(SyntaxError::UnexpectedToken(c))
+                }
+            },
+            _ => return syntax!(SyntaxError::UnexpectedToken(c))
+        },
+        Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+        None => return syntax!(SyntaxError::UnexpectedEnd)
+    };
+
+    it.next();
+    Ok(Some(shape))
+}
+
+/// Parses `--badge TEXT [--badge-style STYLE] [--badge-color COLOR] [--badge-text-color COLOR]
+/// [--badge-min-size SIZE]`.
+pub fn badge(it: &mut TokenStream) -> Result<Badge, Error> {
+    it.next();
+    let text = match it.peek() {
+        Some(&(_, Token::Path(text))) => text.to_string_lossy().into_owned(),
+        Some(&(_, &Token::Size(n))) => n.to_string(),
+        Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+        None => return syntax!(SyntaxError::UnexpectedEnd)
+    };
+
+    it.next();
+    let mut badge = Badge {
+        text,
+        style: Style::Ribbon,
+        color: badge::DEFAULT_COLOR,
+        text_color: badge::DEFAULT_TEXT_COLOR,
+        min_size: badge::DEFAULT_MIN_SIZE
+    };
+
+    if flag(it, Flag::BadgeStyle) {
+        badge.style = match it.peek() {
+            Some(&(c, Token::Path(style))) => match style.to_str() {
+                Some("ribbon") => Style::Ribbon,
+                Some("corner") => Style::Corner,
+                _ => return syntax!(SyntaxError::UnexpectedToken(c))
+            },
+            Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            None => return syntax!(SyntaxError::UnexpectedEnd)
+        };
+
+        it.next();
+    }
+
+    if flag(it, Flag::BadgeColor) {
+        badge.color = color_arg(it)?;
+    }
+
+    if flag(it, Flag::BadgeTextColor) {
+        badge.text_color = color_arg(it)?;
+    }
+
+    if flag(it, Flag::BadgeMinSize) {
+        match it.peek() {
+            Some(&(_, &Token::Size(size))) => { it.next(); badge.min_size = size; },
+            Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            None => return syntax!(SyntaxError::UnexpectedEnd)
+        }
+    }
+
+    Ok(badge)
+}
+
+/// Parses `--color-space (srgb | p3)`. `p3` is only accepted if `wide_gamut` is set,
+/// since only some formats can be tagged with a color space.
+pub fn color_space(it: &mut TokenStream, wide_gamut: bool) -> Result<ColorSpace, Error> {
+    it.next();
+    let color_space = match it.peek() {
+        Some(&(c, Token::Path(path))) => match path.to_str() {
+            Some("srgb") => ColorSpace::Srgb,
+            Some("p3") if wide_gamut => ColorSpace::DisplayP3,
+            _ => return syntax!(SyntaxError::UnexpectedToken(c))
+        },
+        Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+        None => return syntax!(SyntaxError::UnexpectedEnd)
+    };
+
+    it.next();
+    Ok(color_space)
+}
+
+/// Parses `--dark (FILE | invert)`.
+pub fn dark(it: &mut TokenStream) -> Result<Dark, Error> {
+    it.next();
+    match it.peek() {
+        Some(&(_, Token::Path(path))) => {
+            it.next();
+            if path.to_str() == Some("invert") { Ok(Dark::Invert) } else { Ok(Dark::Path(path.clone())) }
+        },
+        Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c)),
+        None => syntax!(SyntaxError::UnexpectedEnd)
+    }
+}
+
+/// Parses `--optimize LEVEL`.
+pub fn optimize(it: &mut TokenStream) -> Result<u8, Error> {
+    it.next();
+    match it.peek() {
+        Some(&(_, &Token::Size(level))) if level >= 1 && level <= u32::from(optimize::MAX_LEVEL) => {
+            it.next();
+            Ok(level as u8)
+        },
+        Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c)),
+        None => syntax!(SyntaxError::UnexpectedEnd)
+    }
+}
+
+/// Parses `--quantize QUALITY`.
+pub fn quantize(it: &mut TokenStream) -> Result<u8, Error> {
+    it.next();
+    match it.peek() {
+        Some(&(_, &Token::Size(quality))) if quality <= 100 => {
+            it.next();
+            Ok(quality as u8)
+        },
+        Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c)),
+        None => syntax!(SyntaxError::UnexpectedEnd)
+    }
+}
+
+/// Parses `--max-bytes BYTES`.
+pub fn max_bytes(it: &mut TokenStream) -> Result<usize, Error> {
+    it.next();
+    match it.peek() {
+        Some(&(_, &Token::Size(bytes))) if bytes > 0 => {
+            it.next();
+            Ok(bytes as usize)
+        },
+        Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c)),
+        None => syntax!(SyntaxError::UnexpectedEnd)
+    }
+}
+
+/// Parses a hex color argument.
+fn color_arg(it: &mut TokenStream) -> Result<Rgba<u8>, Error> {
+    match it.peek() {
+        Some(&(c, Token::Path(path))) => match path.to_str().and_then(color) {
+            Some(color) => { it.next(); Ok(color) },
+            None => syntax!(SyntaxError::UnexpectedToken(c))
+        },
+        Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c)),
+        None => syntax!(SyntaxError::UnexpectedEnd)
+    }
+}
+
+/// Parses hex colors such as `#fff`, `#ffffff` or `#ffffff80`.
+fn color(s: &str) -> Option<Rgba<u8>> {
+    let hex = s.get(1..)?;
+    let digit = |i: usize, len: usize| u8::from_str_radix(hex.get(i..i + len)?, 16).ok();
+
+    match hex.len() {
+        3 => Some(Rgba([digit(0, 1)? * 17, digit(1, 1)? * 17, digit(2, 1)? * 17, 0xFF])),
+        6 => Some(Rgba([digit(0, 2)?, digit(2, 2)?, digit(4, 2)?, 0xFF])),
+        8 => Some(Rgba([digit(0, 2)?, digit(2, 2)?, digit(4, 2)?, digit(6, 2)?])),
+        _ => None
+    }
+}
+
+fn depth(it: &mut TokenStream) -> Result<u32, Error> {
+    if let Some((_, Token::Flag(Flag::Depth))) = it.peek() {
+        it.next();
+        match it.peek() {
+            Some(&(_, &Token::Size(depth))) => { it.next(); return Ok(depth); },
+            Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+            None => return syntax!(SyntaxError::UnexpectedEnd)
+        }
+    }
+
+    Ok(DEFAULT_DEPTH)
+}
+
 #[inline]
 pub fn expect_end(it: &mut TokenStream, command: Command) -> Result<Command, Error> {
     it.next();
@@ -87,10 +645,10 @@ pub fn expect_end(it: &mut TokenStream, command: Command) -> Result<Command, Err
     }
 }
 
-pub fn output<I: Icon, F: 'static + FnMut(Entries<<I as Icon>::Key>, Output) -> Command>(
+pub fn output<K, F: 'static + FnMut(Entries<K>, Output) -> Command>(
     mut constructor: F,
     it: &mut TokenStream,
-    entries: Entries<<I as Icon>::Key>
+    entries: Entries<K>
 ) -> Result<Command, Error> {
     it.next();
     match it.peek() {
@@ -1,6 +1,10 @@
-use crate::{command::{Command, FaviconConfig}, syntax, error::{Error, SyntaxError}, Output};
+use crate::{
+    command::{Command, IcoConfig, IcnsConfig, FaviconConfig},
+    encode, syntax, error::{Error, SyntaxError}, Output, Entries, badge::Badge,
+    color::ColorSpace
+};
 use std::{convert::TryFrom, iter::{Iterator, Peekable, Enumerate}, slice::Iter};
-use icon_baker::{Icon, ico::Ico, icns::Icns, favicon::Favicon};
+use icon_baker::{Icon, favicon::Favicon};
 
 mod combinators;
 mod token;
@@ -11,8 +15,11 @@ use combinators::{entries, expect_end, output};
 type TokenStream<'a> = Peekable<Enumerate<Iter<'a, Token>>>;
 
 pub fn args() -> Result<Command, Error> {
-    let args = crate::args();
+    parse(crate::args())
+}
 
+/// Parses `args`, the command-line arguments without the name of the executable.
+pub fn parse(args: Vec<String>) -> Result<Command, Error> {
     if args.is_empty() { return Ok(Command::Help); }
 
     let tokens = tokens(args);
@@ -33,7 +40,7 @@ pub fn args() -> Result<Command, Error> {
 }
 
 #[inline]
-fn tokens<'a>(args: Vec<String>) -> Vec<Token> {
+fn tokens(args: Vec<String>) -> Vec<Token> {
     args.iter().map(|arg| Token::from(arg.as_ref())).collect()
 }
 
@@ -41,11 +48,15 @@ fn tokens<'a>(args: Vec<String>) -> Vec<Token> {
 fn favicon(it: &mut TokenStream, n_entries: usize) -> Result<Command, Error> {
     let mut web_app = false;
     let mut apple_touch = false;
+    let mut badge = None;
+    let mut color_space = None;
+    let mut dark = None;
+    let mut animated = None;
+    let mut optimize = None;
+    let mut quantize = None;
+    let mut max_bytes = None;
     
-    let entries = entries::<Favicon, _>(
-        <Favicon as Icon>::Key::try_from,
-        it, n_entries
-    )?;
+    let mut entries = entries(true_color::<<Favicon as Icon>::Key>, it, n_entries)?;
 
     while let Some(&(c, Token::Flag(flag))) = it.peek() {
         match flag {
@@ -65,15 +76,97 @@ fn favicon(it: &mut TokenStream, n_entries: usize) -> Result<Command, Error> {
                     return syntax!(SyntaxError::UnexpectedToken(c));
                 }
             },
+            Flag::Badge => {
+                if badge.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                badge = Some(combinators::badge(it)?);
+            },
+            Flag::ColorSpace => {
+                if color_space.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                color_space = Some(combinators::color_space(it, false)?);
+            },
+            Flag::Dark => {
+                if dark.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                dark = Some((c, combinators::dark(it)?));
+            },
+            Flag::Animated => {
+                if animated.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                animated = Some(c);
+                it.next();
+            },
+            Flag::Optimize => {
+                if optimize.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                optimize = Some((c, combinators::optimize(it)?));
+            },
+            Flag::Quantize => {
+                if quantize.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                quantize = Some((c, combinators::quantize(it)?));
+            },
+            Flag::MaxBytes => {
+                if max_bytes.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                max_bytes = Some((c, combinators::max_bytes(it)?));
+            },
             _ => break
         }
     }
 
-    let config = FaviconConfig::new(web_app, apple_touch);
+    if let Some(badge) = badge {
+        with_badge(&mut entries, &badge);
+    }
+
+    let color_space = color_space.unwrap_or(ColorSpace::Srgb);
+    with_color_space(&mut entries, color_space);
+
+    // Dark variants and animated favicons are saved in folders of their own, and
+    // favicons are optimized, quantized and measured once saved, so they require an
+    // output path
+    if it.peek().is_none() {
+        let flags = [
+            dark.as_ref().map(|&(c, _)| c),
+            animated,
+            optimize.map(|(c, _)| c),
+            quantize.map(|(c, _)| c),
+            max_bytes.map(|(c, _)| c)
+        ];
+
+        if let Some(&c) = flags.iter().flatten().next() {
+            return syntax!(SyntaxError::UnexpectedToken(c));
+        }
+    }
+
+    let config = FaviconConfig::new(
+        web_app,
+        apple_touch,
+        dark.map(|(_, dark)| dark),
+        animated.is_some(),
+        optimize.map(|(_, level)| level),
+        quantize.map(|(_, quality)| quality),
+        max_bytes.map(|(_, bytes)| bytes)
+    );
 
     match it.peek() {
         Some((_, Token::Flag(Flag::Output))) => {
-            output::<Favicon, _>(move |entries, out| Command::Favicon(entries, config, out), it, entries)
+            output(move |entries, out| Command::Favicon(entries, config.clone(), out), it, entries)
         },
         None => Ok(Command::Favicon(entries, config, Output::Stdout)),
         Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c))
@@ -82,28 +175,194 @@ fn favicon(it: &mut TokenStream, n_entries: usize) -> Result<Command, Error> {
 
 #[inline]
 fn icns(it: &mut TokenStream, n_entries: usize) -> Result<Command, Error> {
-    let entries = entries::<Icns, _>(
-        <Icns as Icon>::Key::try_from,
-        it, n_entries
-    )?;
+    let mut dither = false;
+    let mut macos_style = false;
+    let mut badge = None;
+    let mut color_space = None;
+    let mut optimize = None;
+    let mut max_bytes = None;
+
+    let mut entries = entries(encode::icns::Key::new, it, n_entries)?;
+
+    while let Some(&(c, Token::Flag(flag))) = it.peek() {
+        match flag {
+            Flag::Dither => {
+                if !dither {
+                    dither = true;
+                    it.next();
+                } else {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+            },
+            Flag::MacosStyle => {
+                if !macos_style {
+                    macos_style = true;
+                    it.next();
+                } else {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+            },
+            Flag::Badge => {
+                if badge.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                badge = Some(combinators::badge(it)?);
+            },
+            Flag::ColorSpace => {
+                if color_space.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                color_space = Some(combinators::color_space(it, true)?);
+            },
+            Flag::Optimize => {
+                if optimize.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                optimize = Some(combinators::optimize(it)?);
+            },
+            Flag::MaxBytes => {
+                if max_bytes.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                max_bytes = Some(combinators::max_bytes(it)?);
+            },
+            _ => break
+        }
+    }
+
+    if let Some(badge) = badge {
+        with_badge(&mut entries, &badge);
+    }
+
+    let color_space = color_space.unwrap_or(ColorSpace::Srgb);
+    with_color_space(&mut entries, color_space);
+
+    let config = IcnsConfig::new(dither, macos_style, color_space, optimize, max_bytes);
 
     match it.peek() {
-        Some((_, Token::Flag(Flag::Output))) => output::<Icns, _>(Command::Icns, it, entries),
-        None => Ok(Command::Icns(entries, Output::Stdout)),
+        Some((_, Token::Flag(Flag::Output))) => {
+            output(move |entries, out| Command::Icns(entries, config, out), it, entries)
+        },
+        None => Ok(Command::Icns(entries, config, Output::Stdout)),
         Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c))
     }
 }
 
 #[inline]
 fn ico(it: &mut TokenStream, n_entries: usize) -> Result<Command, Error> {
-    let entries = entries::<Ico, _>(
-        <Ico as Icon>::Key::try_from,
-        it, n_entries
-    )?;
+    let mut bmp_below = None;
+    let mut dither = false;
+    let mut badge = None;
+    let mut color_space = None;
+    let mut optimize = None;
+    let mut max_bytes = None;
+
+    let mut entries = entries(encode::ico::Key::new, it, n_entries)?;
+
+    while let Some(&(c, Token::Flag(flag))) = it.peek() {
+        match flag {
+            Flag::BmpBelow => {
+                if bmp_below.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                it.next();
+                match it.peek() {
+                    Some(&(_, &Token::Size(size))) => { it.next(); bmp_below = Some(size); },
+                    Some(&(c, _)) => return syntax!(SyntaxError::UnexpectedToken(c)),
+                    None => return syntax!(SyntaxError::UnexpectedEnd)
+                }
+            },
+            Flag::Dither => {
+                if !dither {
+                    dither = true;
+                    it.next();
+                } else {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+            },
+            Flag::Badge => {
+                if badge.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                badge = Some(combinators::badge(it)?);
+            },
+            Flag::ColorSpace => {
+                if color_space.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                color_space = Some(combinators::color_space(it, false)?);
+            },
+            Flag::Optimize => {
+                if optimize.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                optimize = Some(combinators::optimize(it)?);
+            },
+            Flag::MaxBytes => {
+                if max_bytes.is_some() {
+                    return syntax!(SyntaxError::UnexpectedToken(c));
+                }
+
+                max_bytes = Some(combinators::max_bytes(it)?);
+            },
+            _ => break
+        }
+    }
+
+    if let Some(badge) = badge {
+        with_badge(&mut entries, &badge);
+    }
+
+    let color_space = color_space.unwrap_or(ColorSpace::Srgb);
+    with_color_space(&mut entries, color_space);
+
+    let config = IcoConfig::new(
+        bmp_below.unwrap_or(encode::ico::DEFAULT_BMP_BELOW),
+        dither,
+        optimize,
+        max_bytes
+    );
 
     match it.peek() {
-        Some((_, Token::Flag(Flag::Output))) => output::<Ico, _>(Command::Ico, it, entries),
-        None => Ok(Command::Ico(entries, Output::Stdout)),
+        Some((_, Token::Flag(Flag::Output))) => {
+            output(move |entries, out| Command::Ico(entries, config, out), it, entries)
+        },
+        None => Ok(Command::Ico(entries, config, Output::Stdout)),
         Some(&(c, _)) => syntax!(SyntaxError::UnexpectedToken(c))
     }
+}
+
+/// Overlays `badge` on every entry.
+fn with_badge<K>(entries: &mut Entries<K>, badge: &Badge) {
+    for (_, _, config) in entries.iter_mut() {
+        config.badge = Some(badge.clone());
+    }
+}
+
+/// Converts the sources of every entry to `color_space`.
+fn with_color_space<K>(entries: &mut Entries<K>, color_space: ColorSpace) {
+    for (_, _, config) in entries.iter_mut() {
+        config.color_space = color_space;
+    }
+}
+
+/// Converts `size` to a `K`, for icon formats that only support unscaled 32-bit entries.
+fn true_color<K: TryFrom<u32>>(size: u32, scale: u32, depth: u32) -> Result<K, Error> {
+    if scale != 1 {
+        return Err(Error::InvalidScale(size, scale));
+    }
+
+    if depth != 32 {
+        return Err(Error::InvalidDepth(depth));
+    }
+
+    K::try_from(size).map_err(|_| Error::InvalidDimensions(size))
 }
\ No newline at end of file
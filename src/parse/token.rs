@@ -7,6 +7,8 @@ pub enum Token {
     Command(Cmd),
     Path(PathBuf),
     Size(u32),
+    /// A size followed by a scale factor, such as `512@2x`.
+    ScaledSize(u32, u32),
     Filter(ResamplingFilter)
 }
 
@@ -23,12 +25,43 @@ pub enum Flag {
     Help,
     Version,
     Resample,
+    LinearLight,
+    Sharpen,
+    Trim,
+    Padding,
+    Fit,
+    Align,
+    Background,
+    Shape,
+    Layer,
+    Offset,
+    Scale,
+    Opacity,
     Output,
+    Depth,
+    BmpBelow,
+    Dither,
+    MacosStyle,
+    Badge,
+    BadgeStyle,
+    BadgeColor,
+    BadgeTextColor,
+    BadgeMinSize,
+    Font,
+    TextColor,
+    ColorSpace,
+    Dark,
+    Monochrome,
+    PixelArt,
+    Animated,
+    Optimize,
+    Quantize,
+    MaxBytes,
     AppleTouch,
     WebApp
 }
 
-impl<'a> From<&'a str> for Token {
+impl From<&str> for Token {
     fn from(s: &str) -> Self {
         match s {
             "ico" => Token::Command(Cmd::Ico),
@@ -36,17 +69,54 @@ impl<'a> From<&'a str> for Token {
             "favicon" => Token::Command(Cmd::Favicon),
             "-e" | "--entry" => Token::Flag(Flag::Entry),
             "-r" | "--resample" => Token::Flag(Flag::Resample),
+            "--linear-light" => Token::Flag(Flag::LinearLight),
+            "--sharpen" => Token::Flag(Flag::Sharpen),
+            "--trim" => Token::Flag(Flag::Trim),
+            "--padding" => Token::Flag(Flag::Padding),
+            "--fit" => Token::Flag(Flag::Fit),
+            "--align" => Token::Flag(Flag::Align),
+            "--background" => Token::Flag(Flag::Background),
+            "--shape" => Token::Flag(Flag::Shape),
+            "--layer" => Token::Flag(Flag::Layer),
+            "--offset" => Token::Flag(Flag::Offset),
+            "--scale" => Token::Flag(Flag::Scale),
+            "--opacity" => Token::Flag(Flag::Opacity),
             "nearest" => Token::Filter(ResamplingFilter::Nearest),
             "linear" => Token::Filter(ResamplingFilter::Linear),
             "cubic" => Token::Filter(ResamplingFilter::Cubic),
+            "lanczos2" => Token::Filter(ResamplingFilter::Lanczos2),
+            "lanczos3" => Token::Filter(ResamplingFilter::Lanczos3),
+            "mitchell" => Token::Filter(ResamplingFilter::Mitchell),
+            "catmull-rom" => Token::Filter(ResamplingFilter::CatmullRom),
             "-h" | "--help" => Token::Flag(Flag::Help),
             "-v" | "--version" => Token::Flag(Flag::Version),
             "-o" | "--output" => Token::Flag(Flag::Output),
+            "--depth" => Token::Flag(Flag::Depth),
+            "--bmp-below" => Token::Flag(Flag::BmpBelow),
+            "--dither" => Token::Flag(Flag::Dither),
+            "--macos-style" => Token::Flag(Flag::MacosStyle),
+            "--badge" => Token::Flag(Flag::Badge),
+            "--badge-style" => Token::Flag(Flag::BadgeStyle),
+            "--badge-color" => Token::Flag(Flag::BadgeColor),
+            "--badge-text-color" => Token::Flag(Flag::BadgeTextColor),
+            "--badge-min-size" => Token::Flag(Flag::BadgeMinSize),
+            "--font" => Token::Flag(Flag::Font),
+            "--text-color" => Token::Flag(Flag::TextColor),
+            "--color-space" => Token::Flag(Flag::ColorSpace),
+            "--dark" => Token::Flag(Flag::Dark),
+            "--monochrome" => Token::Flag(Flag::Monochrome),
+            "--pixel-art" => Token::Flag(Flag::PixelArt),
+            "--animated" => Token::Flag(Flag::Animated),
+            "--optimize" => Token::Flag(Flag::Optimize),
+            "--quantize" => Token::Flag(Flag::Quantize),
+            "--max-bytes" => Token::Flag(Flag::MaxBytes),
             "--apple-touch" => Token::Flag(Flag::AppleTouch),
             "--web-app" => Token::Flag(Flag::WebApp),
             _ => {
                 if let Ok(size) = s.parse::<u32>() {
                     Token::Size(size)
+                } else if let Some((size, scale)) = scaled_size(s) {
+                    Token::ScaledSize(size, scale)
                 } else {
                     Token::Path(PathBuf::from(s))
                 }
@@ -54,3 +124,14 @@ impl<'a> From<&'a str> for Token {
         }
     }
 }
+
+/// Parses strings such as `512@2x`.
+fn scaled_size(s: &str) -> Option<(u32, u32)> {
+    if !s.ends_with('x') { return None; }
+
+    let mut parts = s[..s.len() - 1].splitn(2, '@');
+    let size = parts.next()?.parse::<u32>().ok()?;
+    let scale = parts.next()?.parse::<u32>().ok()?;
+
+    Some((size, scale))
+}
@@ -0,0 +1,157 @@
+use crate::error::RenderError;
+use std::io;
+use icon_baker::{SourceImage, image::{DynamicImage, GenericImage, RgbaImage, Rgba}};
+
+/// Scales pixel art by integer factors only, so that every source pixel maps to a
+/// whole block of pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PixelArt {
+    /// Whether sizes that aren't integer multiples of the source are scaled by the
+    /// largest integer factor that fits and padded, rather than rejected.
+    pub snap: bool,
+    pub upscaler: Upscaler
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Upscaler {
+    /// Every pixel is repeated.
+    Nearest,
+    /// Edges are smoothed by Scale2x and Scale3x, for factors of `2` and `3`
+    /// respectively. The remaining factor is applied by repeating pixels.
+    Scale2x
+}
+
+impl PixelArt {
+    /// Scales `source` to fit a _`size`x`size`_ canvas and centers it. Fails if
+    /// `source` would have to be downscaled, or scaled by a fractional factor without
+    /// `snap`.
+    // `is_multiple_of` would require Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn resample(&self, source: &SourceImage, size: u32) -> io::Result<DynamicImage> {
+        let image = match source {
+            SourceImage::Raster(image) => image.to_rgba(),
+            SourceImage::Svg(_) => return Err(RenderError(String::from("pixel art sources must be raster images")).into())
+        };
+
+        let (width, height) = image.dimensions();
+        let side = width.max(height);
+
+        if size < side {
+            return Err(RenderError(format!(
+                "pixel art can't be downscaled from {0}x{0} to {1}x{1}", side, size
+            )).into());
+        }
+
+        if size % side != 0 && !self.snap {
+            return Err(RenderError(format!(
+                "{1}x{1} is not an integer multiple of {0}x{0}", side, size
+            )).into());
+        }
+
+        let factor = size / side;
+        let scaled = self.upscaler.scale(&image, factor);
+
+        let mut output = RgbaImage::new(size, size);
+        output.copy_from(&scaled, (size - width * factor) / 2, (size - height * factor) / 2);
+
+        Ok(DynamicImage::ImageRgba8(output))
+    }
+}
+
+impl Upscaler {
+    #[allow(clippy::manual_is_multiple_of)]
+    fn scale(self, image: &RgbaImage, mut factor: u32) -> RgbaImage {
+        let mut image = image.clone();
+
+        if self == Upscaler::Scale2x {
+            while factor % 2 == 0 {
+                image = scale2x(&image);
+                factor /= 2;
+            }
+
+            while factor % 3 == 0 {
+                image = scale3x(&image);
+                factor /= 3;
+            }
+        }
+
+        if factor == 1 {
+            image
+        } else {
+            let (width, height) = image.dimensions();
+            RgbaImage::from_fn(width * factor, height * factor, |x, y| {
+                *image.get_pixel(x / factor, y / factor)
+            })
+        }
+    }
+}
+
+/// Doubles the size of `image` with the Scale2x algorithm.
+fn scale2x(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let mut output = RgbaImage::new(width * 2, height * 2);
+
+    for y in 0..height {
+        for x in 0..width {
+            let [_, b, _, d, e, f, _, h, _] = neighbors(image, x, y);
+
+            let block = [
+                if d == b && b != f && d != h { d } else { e },
+                if b == f && b != d && f != h { f } else { e },
+                if d == h && d != b && h != f { d } else { e },
+                if h == f && d != h && b != f { f } else { e }
+            ];
+
+            for (i, px) in block.iter().enumerate() {
+                output.put_pixel(2 * x + i as u32 % 2, 2 * y + i as u32 / 2, *px);
+            }
+        }
+    }
+
+    output
+}
+
+/// Triples the size of `image` with the Scale3x algorithm.
+fn scale3x(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let mut output = RgbaImage::new(width * 3, height * 3);
+
+    for y in 0..height {
+        for x in 0..width {
+            let [a, b, c, d, e, f, g, h, i] = neighbors(image, x, y);
+
+            let block = [
+                if d == b && b != f && d != h { d } else { e },
+                if (d == b && b != f && d != h && e != c) || (b == f && b != d && f != h && e != a) { b } else { e },
+                if b == f && b != d && f != h { f } else { e },
+                if (d == b && b != f && d != h && e != g) || (d == h && d != b && h != f && e != a) { d } else { e },
+                e,
+                if (b == f && b != d && f != h && e != i) || (h == f && d != h && b != f && e != c) { f } else { e },
+                if d == h && d != b && h != f { d } else { e },
+                if (d == h && d != b && h != f && e != i) || (h == f && d != h && b != f && e != g) { h } else { e },
+                if h == f && d != h && b != f { f } else { e }
+            ];
+
+            for (j, px) in block.iter().enumerate() {
+                output.put_pixel(3 * x + j as u32 % 3, 3 * y + j as u32 / 3, *px);
+            }
+        }
+    }
+
+    output
+}
+
+/// The _3x3_ neighborhood of the pixel at `(x, y)`, in row-major order. Pixels past
+/// the edges of `image` are clamped to them.
+fn neighbors(image: &RgbaImage, x: u32, y: u32) -> [Rgba<u8>;9] {
+    let (width, height) = image.dimensions();
+    let xs = [x.saturating_sub(1), x, (x + 1).min(width - 1)];
+    let ys = [y.saturating_sub(1), y, (y + 1).min(height - 1)];
+
+    let mut output = [Rgba([0;4]);9];
+    for (i, px) in output.iter_mut().enumerate() {
+        *px = *image.get_pixel(xs[i % 3], ys[i / 3]);
+    }
+
+    output
+}
\ No newline at end of file
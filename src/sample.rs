@@ -0,0 +1,420 @@
+use std::{io, f32::consts::PI};
+use icon_baker::{
+    resample, SourceImage,
+    image::{DynamicImage, GenericImageView, RgbaImage, Rgba, imageops}
+};
+
+/// A separable reconstruction filter.
+pub struct Kernel {
+    /// The radius of the filter, in source pixels.
+    support: f32,
+    weight: fn(f32) -> f32
+}
+
+pub const TRIANGLE: Kernel = Kernel { support: 1.0, weight: triangle };
+pub const LANCZOS2: Kernel = Kernel { support: 2.0, weight: lanczos2 };
+pub const LANCZOS3: Kernel = Kernel { support: 3.0, weight: lanczos3 };
+pub const CUBIC: Kernel = Kernel { support: 2.0, weight: cubic };
+pub const MITCHELL: Kernel = Kernel { support: 2.0, weight: mitchell };
+pub const CATMULL_ROM: Kernel = Kernel { support: 2.0, weight: catmull_rom };
+
+/// Entries smaller than this are sharpened by `Sharpen::Auto`.
+pub const AUTO_SHARPEN_BELOW: u32 = 48;
+
+/// Whether to apply an unsharp mask to an entry after it is resampled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sharpen {
+    None,
+    /// Sharpens entries smaller than `AUTO_SHARPEN_BELOW` with a mild mask.
+    Auto,
+    Manual { amount: f32, radius: f32 }
+}
+
+/// An image of premultiplied RGBA pixels with floating point channels.
+struct Buffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32;4]>
+}
+
+/// Resamples `source` with `kernel`, preserving its aspect ratio, and centers the
+/// result on a transparent _`size`x`size`_ canvas.
+///
+/// Pixels are blended with premultiplied alpha, so that the color of transparent
+/// pixels doesn't bleed into the edges of the image. If `linear_light` is set they
+/// are also blended in linear light, rather than in sRGB space.
+///
+/// Vector graphics are always rasterized with linear interpolation.
+pub fn resample(
+    source: &SourceImage,
+    size: u32,
+    kernel: &Kernel,
+    linear_light: bool
+) -> io::Result<DynamicImage> {
+    let image = match source {
+        SourceImage::Raster(image) => image,
+        SourceImage::Svg(_) => return resample::linear(source, size)
+    };
+
+    let (width, height) = image.dimensions();
+    let (new_width, new_height) = if width >= height {
+        (size, ((height as f32 * size as f32 / width as f32).round() as u32).max(1))
+    } else {
+        (((width as f32 * size as f32 / height as f32).round() as u32).max(1), size)
+    };
+
+    let scaled = resize(&image.to_rgba(), new_width, new_height, kernel, linear_light);
+    let mut output = RgbaImage::new(size, size);
+    imageops::overlay(&mut output, &scaled, (size - new_width) / 2, (size - new_height) / 2);
+
+    Ok(DynamicImage::ImageRgba8(output))
+}
+
+/// Resamples `image` with `kernel` to _`new_width`x`new_height`_, disregarding its
+/// aspect ratio.
+pub fn resize(
+    image: &RgbaImage,
+    new_width: u32,
+    new_height: u32,
+    kernel: &Kernel,
+    linear_light: bool
+) -> RgbaImage {
+    Buffer::from_rgba(image, linear_light)
+        .horizontal(&weights(image.width(), new_width, kernel))
+        .vertical(&weights(image.height(), new_height, kernel))
+        .into_rgba(linear_light)
+}
+
+/// Applies an unsharp mask to `image`: the difference between `image` and a gaussian
+/// blur of `radius` pixels is scaled by `amount` and added back to `image`.
+pub fn sharpen(image: &RgbaImage, amount: f32, radius: f32) -> RgbaImage {
+    let mut buffer = Buffer::from_rgba(image, false);
+    let blurred = buffer
+        .horizontal(&gaussian(buffer.width, radius))
+        .vertical(&gaussian(buffer.height, radius));
+
+    for (px, blur) in buffer.pixels.iter_mut().zip(blurred.pixels.iter()) {
+        let alpha = px[3];
+
+        // Colors are premultiplied, so they can't exceed the alpha of the pixel
+        for (c, b) in px.iter_mut().zip(blur.iter()).take(3) {
+            *c = (*c + amount * (*c - b)).max(0.0).min(alpha);
+        }
+    }
+
+    buffer.into_rgba(false)
+}
+
+/// Applies a gaussian blur of standard deviation `sigma` pixels to `image`.
+pub fn blur(image: &RgbaImage, sigma: f32) -> RgbaImage {
+    Buffer::from_rgba(image, false)
+        .horizontal(&gaussian(image.width(), sigma))
+        .vertical(&gaussian(image.height(), sigma))
+        .into_rgba(false)
+}
+
+impl Sharpen {
+    /// The amount and radius of the unsharp mask applied to entries of _`size`x`size`_.
+    pub fn params(self, size: u32) -> Option<(f32, f32)> {
+        match self {
+            Sharpen::None => None,
+            Sharpen::Auto if size < AUTO_SHARPEN_BELOW => Some((0.5, 0.5)),
+            Sharpen::Auto => None,
+            Sharpen::Manual { amount, radius } => Some((amount, radius))
+        }
+    }
+}
+
+impl Buffer {
+    fn from_rgba(image: &RgbaImage, linear_light: bool) -> Self {
+        let decode: Vec<f32> = (0..=255u8)
+            .map(|c| if linear_light { to_linear(c) } else { f32::from(c) / 255.0 })
+            .collect();
+
+        let pixels = image.pixels().map(|px| {
+            let alpha = f32::from(px.0[3]) / 255.0;
+
+            [
+                decode[px.0[0] as usize] * alpha,
+                decode[px.0[1] as usize] * alpha,
+                decode[px.0[2] as usize] * alpha,
+                alpha
+            ]
+        }).collect();
+
+        Buffer { width: image.width(), height: image.height(), pixels }
+    }
+
+    fn into_rgba(self, linear_light: bool) -> RgbaImage {
+        let mut output = RgbaImage::new(self.width, self.height);
+
+        for (px, &[r, g, b, a]) in output.pixels_mut().zip(self.pixels.iter()) {
+            let alpha = a.clamp(0.0, 1.0);
+            if alpha == 0.0 { continue; }
+
+            let encode = |c: f32| {
+                let c = (c / alpha).clamp(0.0, 1.0);
+                if linear_light { from_linear(c) } else { (c * 255.0).round() as u8 }
+            };
+
+            *px = Rgba([encode(r), encode(g), encode(b), (alpha * 255.0).round() as u8]);
+        }
+
+        output
+    }
+
+    /// Convolves each row of the buffer with `weights`, as computed by `weights`.
+    fn horizontal(&self, weights: &[(u32, Vec<f32>)]) -> Self {
+        let new_width = weights.len() as u32;
+        let mut pixels = Vec::with_capacity((new_width * self.height) as usize);
+
+        for row in self.pixels.chunks(self.width as usize) {
+            for (start, w) in weights.iter() {
+                pixels.push(convolve(row[*start as usize..].iter().zip(w.iter())));
+            }
+        }
+
+        Buffer { width: new_width, height: self.height, pixels }
+    }
+
+    /// Convolves each column of the buffer with `weights`, as computed by `weights`.
+    fn vertical(&self, weights: &[(u32, Vec<f32>)]) -> Self {
+        let new_height = weights.len() as u32;
+        let width = self.width as usize;
+        let mut pixels = Vec::with_capacity((self.width * new_height) as usize);
+
+        for (start, w) in weights.iter() {
+            for x in 0..width {
+                let column = self.pixels[*start as usize * width + x..].iter().step_by(width);
+                pixels.push(convolve(column.zip(w.iter())));
+            }
+        }
+
+        Buffer { width: self.width, height: new_height, pixels }
+    }
+}
+
+/// Computes, for each destination pixel, the index of the first contributing source
+/// pixel and the normalized weights of all contributing source pixels.
+fn weights(len: u32, new_len: u32, kernel: &Kernel) -> Vec<(u32, Vec<f32>)> {
+    let ratio = len as f32 / new_len as f32;
+    // When downscaling the kernel is stretched, so that it acts as a low-pass filter
+    let scale = ratio.max(1.0);
+    let support = kernel.support * scale;
+
+    (0..new_len).map(|i| {
+        let center = (i as f32 + 0.5) * ratio;
+        let start = (center - support).floor().max(0.0) as u32;
+        let end = ((center + support).ceil() as u32).min(len);
+
+        let mut row: Vec<f32> = (start..end)
+            .map(|j| (kernel.weight)((j as f32 + 0.5 - center) / scale))
+            .collect();
+
+        let sum: f32 = row.iter().sum();
+        if sum != 0.0 {
+            for w in row.iter_mut() { *w /= sum; }
+        }
+
+        (start, row)
+    }).collect()
+}
+
+/// Computes the weights of a gaussian blur of standard deviation `sigma`, in the
+/// format of `weights`.
+fn gaussian(len: u32, sigma: f32) -> Vec<(u32, Vec<f32>)> {
+    let support = (3.0 * sigma).ceil().max(1.0) as u32;
+
+    (0..len).map(|i| {
+        let start = i.saturating_sub(support);
+        let end = (i + support + 1).min(len);
+
+        let mut row: Vec<f32> = (start..end)
+            .map(|j| (-((j as f32 - i as f32).powi(2)) / (2.0 * sigma * sigma)).exp())
+            .collect();
+
+        let sum: f32 = row.iter().sum();
+        for w in row.iter_mut() { *w /= sum; }
+
+        (start, row)
+    }).collect()
+}
+
+fn convolve<'a, I: Iterator<Item = (&'a [f32;4], &'a f32)>>(pixels: I) -> [f32;4] {
+    let mut sum = [0f32;4];
+
+    for (px, w) in pixels {
+        for (s, c) in sum.iter_mut().zip(px.iter()) {
+            *s += c * w;
+        }
+    }
+
+    sum
+}
+
+/// Decodes an sRGB encoded channel to linear light.
+pub fn to_linear(c: u8) -> f32 {
+    let c = f32::from(c) / 255.0;
+
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a channel in linear light to sRGB.
+pub fn from_linear(c: f32) -> u8 {
+    let c = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+
+    (c * 255.0).round() as u8
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn lanczos(x: f32, a: f32) -> f32 {
+    if x.abs() < a {
+        sinc(x) * sinc(x / a)
+    } else {
+        0.0
+    }
+}
+
+fn triangle(x: f32) -> f32 {
+    (1.0 - x.abs()).max(0.0)
+}
+
+fn lanczos2(x: f32) -> f32 {
+    lanczos(x, 2.0)
+}
+
+fn lanczos3(x: f32) -> f32 {
+    lanczos(x, 3.0)
+}
+
+/// The Mitchell-Netravali family of cubic filters.
+fn bicubic(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2) + (6.0 - 2.0 * b)) / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x.powi(3) + (6.0 * b + 30.0 * c) * x.powi(2) + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// Keys' cubic convolution with `a = -0.75`, as used by most image editors. Sharper
+/// than Catmull-Rom, which is the same filter with `a = -0.5`.
+fn cubic(x: f32) -> f32 {
+    bicubic(x, 0.0, 0.75)
+}
+
+fn mitchell(x: f32) -> f32 {
+    bicubic(x, 1.0 / 3.0, 1.0 / 3.0)
+}
+
+fn catmull_rom(x: f32) -> f32 {
+    bicubic(x, 0.0, 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResamplingFilter;
+    use std::path::Path;
+    use icon_baker::image;
+
+    const FILTERS: [ResamplingFilter;6] = [
+        ResamplingFilter::Linear,
+        ResamplingFilter::Cubic,
+        ResamplingFilter::Lanczos2,
+        ResamplingFilter::Lanczos3,
+        ResamplingFilter::Mitchell,
+        ResamplingFilter::CatmullRom
+    ];
+
+    /// The names of `FILTERS` on the command line.
+    const NAMES: [&str;6] = ["linear", "cubic", "lanczos2", "lanczos3", "mitchell", "catmull-rom"];
+
+    /// An _8x6_ image with gradients, hard edges and translucent pixels.
+    fn pattern() -> SourceImage {
+        let image = RgbaImage::from_fn(8, 6, |x, y| {
+            let (x, y) = (x as u8, y as u8);
+            let alpha = if (x + y) % 3 == 0 { 0 } else { 0xFF - 0x18 * x };
+
+            Rgba([0x20 * x, 0x2A * y, if x < 4 { 0xFF } else { 0x40 }, alpha])
+        });
+
+        SourceImage::Raster(DynamicImage::ImageRgba8(image))
+    }
+
+    /// A transparent black _16x16_ image with a 1px opaque white column.
+    fn edge() -> SourceImage {
+        let image = RgbaImage::from_fn(16, 16, |x, _| {
+            if x == 7 { Rgba([0xFF;4]) } else { Rgba([0;4]) }
+        });
+
+        SourceImage::Raster(DynamicImage::ImageRgba8(image))
+    }
+
+    #[test]
+    fn edges_have_no_dark_fringe() {
+        let source = edge();
+
+        for filter in FILTERS.iter() {
+            for &linear_light in &[false, true] {
+                let output = filter.call(&source, 4, linear_light).unwrap().to_rgba();
+
+                assert!(output.pixels().any(|px| px.0[3] > 0));
+                for px in output.pixels().filter(|px| px.0[3] > 0) {
+                    assert_eq!(&px.0[..3], &[0xFF;3], "{:?}, linear light: {}", filter, linear_light);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filters_match_reference_outputs() {
+        let source = pattern();
+        let folder = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("reference");
+
+        for (filter, name) in FILTERS.iter().zip(NAMES.iter()) {
+            for &(size, linear_light) in &[(5, false), (13, false), (5, true)] {
+                let output = filter.call(&source, size, linear_light).unwrap().to_rgba();
+                let file = format!("{}-{}{}.png", name, size, if linear_light { "-linear-light" } else { "" });
+                let reference = image::open(folder.join(&file)).unwrap().to_rgba();
+
+                assert_eq!(output.dimensions(), reference.dimensions(), "{}", file);
+
+                // Floating point rounding may differ slightly between platforms
+                for (a, b) in output.pixels().zip(reference.pixels()) {
+                    let close = a.0.iter().zip(b.0.iter()).all(|(a, b)| (i16::from(*a) - i16::from(*b)).abs() <= 1);
+                    assert!(close, "{}: {:?} != {:?}", file, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transparent_pixels_dont_bleed() {
+        // Without premultiplied alpha the red channel would be halved as well
+        let image = RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 { Rgba([0xFF, 0, 0, 0xFF]) } else { Rgba([0;4]) }
+        });
+
+        let output = resize(&image, 1, 1, &TRIANGLE, false);
+        assert_eq!(output.get_pixel(0, 0), &Rgba([0xFF, 0, 0, 0x80]));
+    }
+}
\ No newline at end of file